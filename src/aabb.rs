@@ -0,0 +1,160 @@
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::vector3::Point3;
+
+// Axis-aligned bounding box, stored as one interval per axis
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb { x: Interval::EMPTY, y: Interval::EMPTY, z: Interval::EMPTY };
+
+    pub fn new(x: Interval, y: Interval, z: Interval) -> Aabb {
+        Aabb { x, y, z }.pad_to_minimums()
+    }
+
+    // box with a and b as opposite corners, in any order
+    pub fn from_points(a: Point3, b: Point3) -> Aabb {
+        Aabb::new(
+            Interval::new(a.x.min(b.x), a.x.max(b.x)),
+            Interval::new(a.y.min(b.y), a.y.max(b.y)),
+            Interval::new(a.z.min(b.z), a.z.max(b.z))
+        )
+    }
+
+    // smallest box containing both a and b
+    pub fn union(a: Aabb, b: Aabb) -> Aabb {
+        Aabb {
+            x: Interval::union(a.x, b.x),
+            y: Interval::union(a.y, b.y),
+            z: Interval::union(a.z, b.z)
+        }
+    }
+
+    pub fn axis(&self, n: usize) -> Interval {
+        match n {
+            1 => self.y,
+            2 => self.z,
+            _ => self.x
+        }
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() { 0 } else { 2 }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(
+            (self.x.min + self.x.max) / 2.0,
+            (self.y.min + self.y.max) / 2.0,
+            (self.z.min + self.z.max) / 2.0
+        )
+    }
+
+    // Slab test: narrows ray_t to the overlap of the ray with each axis' slab, and misses as soon
+    // as that overlap is empty
+    pub fn hit(&self, ray: &Ray, mut ray_t: Interval) -> bool {
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+
+        for axis in 0..3 {
+            let slab = self.axis(axis);
+            let inverse_direction = 1.0 / direction[axis];
+
+            let t0 = (slab.min - origin[axis]) * inverse_direction;
+            let t1 = (slab.max - origin[axis]) * inverse_direction;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            if t0 > ray_t.min { ray_t.min = t0; }
+            if t1 < ray_t.max { ray_t.max = t1; }
+
+            if ray_t.max <= ray_t.min {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // flat shapes (e.g. a triangle in an axis plane) still need a box with some thickness
+    fn pad_to_minimums(self) -> Aabb {
+        let delta = 0.0001;
+
+        Aabb {
+            x: if self.x.size() < delta { self.x.expand(delta) } else { self.x },
+            y: if self.y.size() < delta { self.y.expand(delta) } else { self.y },
+            z: if self.z.size() < delta { self.z.expand(delta) } else { self.z }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::aabb::Aabb;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::material::Matte;
+    use crate::plane::Plane;
+    use crate::ray::Ray;
+    use crate::vector3::{Colour, Point3, Vector3};
+
+    fn unit_box() -> Aabb {
+        Aabb::from_points(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0))
+    }
+
+    fn ray(origin: (f64, f64, f64), direction: (f64, f64, f64)) -> Ray {
+        Ray::new(Point3::new(origin.0, origin.1, origin.2), Vector3::new(direction.0, direction.1, direction.2))
+    }
+
+    const FORWARD: Interval = Interval { min: 0.001, max: f64::INFINITY };
+
+    #[test]
+    fn hits_and_misses() {
+        assert!(unit_box().hit(&ray((0.0, 0.0, 5.0), (0.0, 0.0, -1.0)), FORWARD));
+        assert!(unit_box().hit(&ray((5.0, 4.0, 3.0), (-1.0, -0.8, -0.6)), FORWARD));
+        assert!(!unit_box().hit(&ray((0.0, 2.0, 5.0), (0.0, 0.0, -1.0)), FORWARD));
+
+        // pointing away, or reaching the box only after the interval ends
+        assert!(!unit_box().hit(&ray((0.0, 0.0, 5.0), (0.0, 0.0, 1.0)), FORWARD));
+        assert!(!unit_box().hit(&ray((0.0, 0.0, 5.0), (0.0, 0.0, -1.0)), Interval::new(0.001, 3.0)));
+    }
+
+    #[test]
+    fn rays_parallel_to_a_slab_hit_only_from_within_it() {
+        // the zero direction components make infinite slab distances, which must not become NaN
+        assert!(unit_box().hit(&ray((0.5, -0.5, 5.0), (0.0, 0.0, -1.0)), FORWARD));
+        assert!(!unit_box().hit(&ray((1.5, 0.0, 5.0), (0.0, 0.0, -1.0)), FORWARD));
+        assert!(!unit_box().hit(&ray((0.0, -3.0, 5.0), (0.0, 0.0, -1.0)), FORWARD));
+    }
+
+    #[test]
+    fn rays_starting_inside_hit() {
+        for direction in [(1.0, 0.0, 0.0), (0.0, -1.0, 0.0), (0.3, 0.4, -0.5)] {
+            assert!(unit_box().hit(&ray((0.2, 0.1, -0.3), direction), FORWARD));
+        }
+    }
+
+    #[test]
+    fn plane_boxes_hit_every_ray() {
+        let material = Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)));
+        let everything = Plane::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), material).bounding_box();
+
+        assert!(everything.hit(&ray((0.0, 0.0, 0.0), (0.0, 0.0, -1.0)), FORWARD));
+        assert!(everything.hit(&ray((1e6, -3.0, 2.0), (0.2, 0.5, 0.0)), FORWARD));
+
+        // and unions with them stay infinite
+        let union = Aabb::union(unit_box(), everything);
+        assert!(union.hit(&ray((0.0, 50.0, 0.0), (1.0, 0.0, 0.0)), FORWARD));
+    }
+}
@@ -0,0 +1,175 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::ray::Ray;
+
+// Bounding volume hierarchy: a binary tree of boxes, so a ray only tests the objects whose boxes
+// it passes through instead of every object in the scene
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Option<Arc<dyn Hittable>>,
+    bbox: Aabb
+}
+
+impl BvhNode {
+    pub fn new(list: HittableList) -> BvhNode {
+        BvhNode::build(list.into_objects())
+    }
+
+    // Splits the objects in half along the longest axis of their combined box, ordered by the
+    // centre of each object's own box
+    fn build(mut objects: Vec<Arc<dyn Hittable>>) -> BvhNode {
+        let bbox = objects.iter().fold(Aabb::EMPTY, |bbox, object| Aabb::union(bbox, object.bounding_box()));
+
+        if objects.len() <= 2 {
+            // an empty list still makes a valid (if pointless) tree that never gets hit
+            let right = if objects.len() == 2 { objects.pop() } else { None };
+            let left = objects.pop().unwrap_or_else(|| Arc::new(HittableList::new()));
+
+            return BvhNode { left, right, bbox };
+        }
+
+        let axis = bbox.longest_axis();
+        objects.sort_by(|a, b| box_compare(a.as_ref(), b.as_ref(), axis));
+
+        let right_objects = objects.split_off(objects.len() / 2);
+        let left = Arc::new(BvhNode::build(objects));
+        let right = Arc::new(BvhNode::build(right_objects));
+
+        BvhNode { left, right: Some(right), bbox }
+    }
+}
+
+fn box_compare(a: &dyn Hittable, b: &dyn Hittable, axis: usize) -> Ordering {
+    let a_centre = a.bounding_box().axis(axis);
+    let b_centre = b.bounding_box().axis(axis);
+
+    (a_centre.min + a_centre.max).total_cmp(&(b_centre.min + b_centre.max))
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        if !self.bbox.hit(ray, ray_t) {
+            return None;
+        }
+
+        // anything the right side hits has to be closer than the left side's hit to replace it
+        let left_record = self.left.hit(ray, ray_t);
+        let closest_so_far = left_record.as_ref().map_or(ray_t.max, |record| record.t);
+
+        let right_record = self.right.as_ref()
+            .and_then(|right| right.hit(ray, Interval::new(ray_t.min, closest_so_far)));
+
+        right_record.or(left_record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn any_hit(&self, ray: &Ray, ray_t: Interval) -> bool {
+        if !self.bbox.hit(ray, ray_t) {
+            return false;
+        }
+
+        self.left.any_hit(ray, ray_t) || self.right.as_ref().is_some_and(|right| right.any_hit(ray, ray_t))
+    }
+
+    fn collect_hits(&self, ray: &Ray, ray_t: Interval, hits: &mut Vec<HitRecord>) {
+        if !self.bbox.hit(ray, ray_t) {
+            return;
+        }
+
+        self.left.collect_hits(ray, ray_t, hits);
+        if let Some(right) = &self.right {
+            right.collect_hits(ray, ray_t, hits);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::bvh::BvhNode;
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+    use crate::interval::Interval;
+    use crate::material::{Material, Matte};
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+    use crate::sphere::Sphere;
+    use crate::vector3::{random_in_interval, random_unit_vector, Colour, Point3, Vector3};
+
+    const FORWARD: Interval = Interval { min: 0.001, max: f64::INFINITY };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)))
+    }
+
+    // the same spheres twice, one list for the tree and one to check it against
+    fn random_spheres(count: usize, sampler: &mut Sampler) -> (HittableList, HittableList) {
+        let mut tree = HittableList::new();
+        let mut list = HittableList::new();
+        for _ in 0..count {
+            let center = random_in_interval(Interval::new(-10.0, 10.0), sampler);
+            let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new(center, 0.1 + 1.4 * sampler.random(), material()));
+            tree.add_shared(Arc::clone(&sphere));
+            list.add_shared(sphere);
+        }
+
+        (tree, list)
+    }
+
+    #[test]
+    fn closest_hits_match_the_brute_force_list() {
+        let mut sampler = Sampler::new(7);
+        let (tree, list) = random_spheres(200, &mut sampler);
+        let bvh = BvhNode::new(tree);
+
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = random_in_interval(Interval::new(-15.0, 15.0), &mut sampler);
+            let ray = Ray::new(origin, random_unit_vector(&mut sampler));
+
+            let expected = list.hit(&ray, FORWARD);
+            let actual = bvh.hit(&ray, FORWARD);
+            assert_eq!(actual.as_ref().map(|record| record.t), expected.as_ref().map(|record| record.t));
+            assert_eq!(bvh.any_hit(&ray, FORWARD), expected.is_some());
+
+            hits += expected.is_some() as usize;
+        }
+
+        // enough of both to mean something
+        assert!(hits > 200 && hits < 1800, "{} hits", hits);
+    }
+
+    #[test]
+    fn empty_tree_is_never_hit() {
+        let bvh = BvhNode::new(HittableList::new());
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+
+        assert!(bvh.hit(&ray, FORWARD).is_none());
+        assert!(!bvh.any_hit(&ray, FORWARD));
+        assert!(bvh.all_hits(&ray, FORWARD).is_empty());
+    }
+
+    #[test]
+    fn single_object_tree_matches_the_object() {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, material());
+        let mut list = HittableList::new();
+        list.add(Box::new(Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, material())));
+        let bvh = BvhNode::new(list);
+
+        assert_eq!(bvh.bounding_box(), sphere.bounding_box());
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(bvh.hit(&ray, FORWARD).unwrap().t, 2.0);
+        assert_eq!(bvh.all_hits(&ray, FORWARD).iter().map(|record| record.t).collect::<Vec<_>>(), [2.0, 4.0]);
+
+        let miss = Ray::new(Point3::new(0.0, 2.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        assert!(bvh.hit(&miss, FORWARD).is_none());
+    }
+}
@@ -1,67 +1,302 @@
-use std::fs::File;
-use std::io::Write;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use crate::framebuffer::Framebuffer;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
-use crate::ray::Ray;
-use crate::util;
+use crate::light::Lights;
+use crate::ray::{Ray, RayDifferentials};
+use crate::sampler::Sampler;
 use crate::vector3::{Colour, Point3, random_in_unit_disk, Vector3};
 
-const ASPECT_RATIO: f64 = 16.0 / 9.0;
-const IMAGE_WIDTH: u32 = 1200;
-const IMAGE_HEIGHT: u32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as u32;
-const APERTURE : f64 = 0.1;
-pub const MAX_COLOUR: f64 = 255.99;
-const SAMPLES_PER_PIXEL : i32 = 10; // TODO set to at least 100
-const MAX_DEPTH : i32 = 5; // TODO set to 50
-const VFOV : f64 = 20.0; //vertical view angle (field of view)
-const VUP : Vector3 = Vector3{x: 0.0, y: 1.0, z: 0.0}; // Camera-relative "up" direction
-const DEFOCUS_ANGLE : f64 = 0.6;
-const FOCUS_DIST : f64 = 10.0;
+// Names of the auxiliary layers added to rendered framebuffers when they're asked for
+pub const NORMAL_LAYER: &str = "normal";
+pub const DEPTH_LAYER: &str = "depth";
+
+// Everything needed to set up a camera and render an image with it. The defaults reproduce the
+// original hard-coded render of the random scene.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraSettings {
+    pub aspect_ratio: f64,
+    pub image_width: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub vfov: f64, // vertical view angle (field of view), in degrees
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: Vector3, // camera-relative "up" direction
+    pub defocus_angle: f64, // variation angle of rays through each pixel, in degrees
+    pub focus_dist: f64, // distance from look_from to the plane of perfect focus
+    pub threads: usize, // number of render threads, 0 uses every available core
+    pub tile_size: u32, // width and height of the square tiles the image is split into
+    pub seed: u64, // renders with the same seed and settings produce identical images
+    pub background: Background, // what rays that escape the scene see
+    pub auxiliary_layers: bool // also render the normal and depth layers, at one extra ray per pixel
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 1200,
+            samples_per_pixel: 10, // TODO set to at least 100
+            max_depth: 5, // TODO set to 50
+            vfov: 20.0,
+            look_from: Point3::new(13.0, 2.0, 3.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            vup: Vector3::new(0.0, 1.0, 0.0),
+            defocus_angle: 0.6,
+            focus_dist: 10.0,
+            threads: 0,
+            tile_size: 32,
+            seed: 0,
+            background: Background::Sky,
+            auxiliary_layers: false
+        }
+    }
+}
+
+impl CameraSettings {
+    pub fn image_height(&self) -> u32 {
+        // the small nudge stops an aspect ratio computed as width / height from truncating to a
+        // row short, e.g. 100 / (100 / 11) comes out as 10.999...
+        (self.image_width as f64 / self.aspect_ratio + 1e-6) as u32
+    }
+
+    pub fn validate(&self) -> Result<(), CameraError> {
+        if self.image_width == 0 {
+            return Err(CameraError::ZeroImageWidth);
+        }
+
+        if !(self.aspect_ratio.is_finite() && self.aspect_ratio > 0.0) || self.image_height() == 0 {
+            return Err(CameraError::InvalidAspectRatio(self.aspect_ratio));
+        }
+
+        if self.samples_per_pixel == 0 {
+            return Err(CameraError::ZeroSamplesPerPixel);
+        }
+
+        if !(self.focus_dist.is_finite() && self.focus_dist > 0.0) {
+            return Err(CameraError::InvalidFocusDistance(self.focus_dist));
+        }
+
+        if self.tile_size == 0 {
+            return Err(CameraError::ZeroTileSize);
+        }
+
+        if !(self.vfov > 0.0 && self.vfov < 180.0) {
+            return Err(CameraError::InvalidFieldOfView(self.vfov));
+        }
+
+        // the view direction must have a length and can't be parallel to "up", otherwise there's
+        // no way to build the camera's basis vectors
+        let view = self.look_from - self.look_at;
+        if view.near_zero() || self.vup.cross(view).near_zero() {
+            return Err(CameraError::DegenerateView);
+        }
+
+        Ok(())
+    }
+}
+
+// Fluent alternative to filling in a CameraSettings by hand, e.g.
+// CameraBuilder::new().image_width(400).samples_per_pixel(50).build()
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CameraBuilder {
+    settings: CameraSettings
+}
+
+impl CameraBuilder {
+    pub fn new() -> CameraBuilder {
+        CameraBuilder::default()
+    }
+
+    pub fn from_settings(settings: CameraSettings) -> CameraBuilder {
+        CameraBuilder { settings }
+    }
+
+    pub fn aspect_ratio(mut self, aspect_ratio: f64) -> Self {
+        self.settings.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    pub fn image_width(mut self, image_width: u32) -> Self {
+        self.settings.image_width = image_width;
+        self
+    }
+
+    pub fn samples_per_pixel(mut self, samples_per_pixel: u32) -> Self {
+        self.settings.samples_per_pixel = samples_per_pixel;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: u32) -> Self {
+        self.settings.max_depth = max_depth;
+        self
+    }
+
+    pub fn vfov(mut self, vfov: f64) -> Self {
+        self.settings.vfov = vfov;
+        self
+    }
+
+    pub fn look_from(mut self, look_from: Point3) -> Self {
+        self.settings.look_from = look_from;
+        self
+    }
+
+    pub fn look_at(mut self, look_at: Point3) -> Self {
+        self.settings.look_at = look_at;
+        self
+    }
+
+    pub fn vup(mut self, vup: Vector3) -> Self {
+        self.settings.vup = vup;
+        self
+    }
+
+    pub fn defocus_angle(mut self, defocus_angle: f64) -> Self {
+        self.settings.defocus_angle = defocus_angle;
+        self
+    }
+
+    pub fn focus_dist(mut self, focus_dist: f64) -> Self {
+        self.settings.focus_dist = focus_dist;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.settings.threads = threads;
+        self
+    }
+
+    pub fn tile_size(mut self, tile_size: u32) -> Self {
+        self.settings.tile_size = tile_size;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.settings.seed = seed;
+        self
+    }
+
+    pub fn background(mut self, background: Background) -> Self {
+        self.settings.background = background;
+        self
+    }
+
+    pub fn auxiliary_layers(mut self, auxiliary_layers: bool) -> Self {
+        self.settings.auxiliary_layers = auxiliary_layers;
+        self
+    }
+
+    pub fn settings(&self) -> CameraSettings {
+        self.settings
+    }
+
+    pub fn build(self) -> Result<Camera, CameraError> {
+        Camera::new(self.settings)
+    }
+}
+
+// The light arriving from outside the scene. Interiors lit only by emissive geometry want a black
+// background so nothing leaks in.
+#[derive(Clone, Copy, Debug)]
+pub enum Background {
+    Sky, // the book's white to blue gradient
+    Solid(Colour)
+}
+
+impl Background {
+    pub fn colour(&self, ray: &Ray) -> Colour {
+        match self {
+            Background::Sky => {
+                let unit_direction : Vector3 = Vector3::unit(ray.direction);
+                let a = 0.5 * (unit_direction.y + 1.0);
+
+                Colour::new(1.0, 1.0, 1.0) * (1.0 - a) + Colour::new(0.5, 0.7, 1.0) * a
+            }
+            Background::Solid(colour) => *colour
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraError {
+    ZeroImageWidth,
+    InvalidAspectRatio(f64),
+    ZeroSamplesPerPixel,
+    InvalidFocusDistance(f64),
+    InvalidFieldOfView(f64),
+    ZeroTileSize,
+    DegenerateView
+}
+
+impl fmt::Display for CameraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CameraError::ZeroImageWidth => write!(f, "image width must be at least 1 pixel"),
+            CameraError::InvalidAspectRatio(ratio) => write!(f, "aspect ratio {} does not produce at least one row of pixels", ratio),
+            CameraError::ZeroSamplesPerPixel => write!(f, "samples per pixel must be at least 1"),
+            CameraError::InvalidFocusDistance(dist) => write!(f, "focus distance must be positive, got {}", dist),
+            CameraError::InvalidFieldOfView(vfov) => write!(f, "vertical field of view must be between 0 and 180 degrees, got {}", vfov),
+            CameraError::ZeroTileSize => write!(f, "tile size must be at least 1 pixel"),
+            CameraError::DegenerateView => write!(f, "look_from and look_at must differ and the view direction can't be parallel to vup")
+        }
+    }
+}
+
+impl Error for CameraError {}
 
 pub struct Camera {
     pub origin: Vector3,
-    image_height: f64,
-    image_width: f64,
+    image_height: u32,
+    image_width: u32,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    threads: usize,
+    tile_size: u32,
+    seed: u64,
+    background: Background,
+    auxiliary_layers: bool,
     pub pixel_delta_v: Vector3,
     pub pixel_delta_u: Vector3,
     pub pixel_origin: Vector3,
     defocus_disk_u : Vector3,
     defocus_disk_v : Vector3,
-    defocus_angle : f64,
-    focus_dist : f64,
-    lens_radius : f64
+    defocus_angle : f64
 }
 
 impl Camera {
-    pub fn new(
-        look_from : Point3,
-        look_at : Point3
+    pub fn new(settings: CameraSettings) -> Result<Camera, CameraError> {
+        settings.validate()?;
 
-    ) -> Camera {
         // Initialize
-        let origin = look_from;
-        let lens_radius = APERTURE / 2.0;
-        let defocus_angle = DEFOCUS_ANGLE;
-        let focus_dist = FOCUS_DIST;
+        let origin = settings.look_from;
+        let image_width = settings.image_width;
+        let image_height = settings.image_height();
+        let focus_dist = settings.focus_dist;
 
         //Determine viewport dimensions
-        let theta = VFOV.to_radians();
+        let theta = settings.vfov.to_radians();
         let h = (theta / 2.0).tan();
         let viewport_height = 2.0 * h * focus_dist;
-        let viewport_width = viewport_height * ASPECT_RATIO;
+        let viewport_width = viewport_height * (image_width as f64 / image_height as f64);
 
         //Calculate u,v,w unit basis vectors for the camera coordinate frame
-        let w = (look_from - look_at).unit();
-        let u = VUP.cross(w).unit();
+        let w = (settings.look_from - settings.look_at).unit();
+        let u = settings.vup.cross(w).unit();
         let v = w.cross(u);
 
         //calculate the vectors across the horizontal and down the vertical viewport edges
         let viewport_horizontal = u * viewport_width;
-        let viewport_vertical = v * viewport_height;
+        let viewport_vertical = -v * viewport_height;
 
         //calculate the horizontal and vertical delta vectors from pixel to pixel
-        let pixel_delta_v = viewport_horizontal/IMAGE_WIDTH as f64;
-        let pixel_delta_u = viewport_vertical/IMAGE_HEIGHT as f64;
+        let pixel_delta_u = viewport_horizontal / image_width as f64;
+        let pixel_delta_v = viewport_vertical / image_height as f64;
 
         //calculate the position of the upper left pixel
         let viewport_upper_left = origin
@@ -69,114 +304,569 @@ impl Camera {
             - viewport_horizontal/2.0
             - viewport_vertical/2.0;
 
-        let pixel_origin = viewport_upper_left
-            + viewport_horizontal * pixel_delta_u
-            + viewport_vertical * pixel_delta_v;
+        let pixel_origin = viewport_upper_left + (pixel_delta_u + pixel_delta_v) * 0.5;
 
         // Calculate the camera defocus disk basis vectors.
-        let defocus_radius = focus_dist * f64::tan((defocus_angle / 2.0).to_radians());
+        let defocus_radius = focus_dist * f64::tan((settings.defocus_angle / 2.0).to_radians());
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
-        Camera {
+        Ok(Camera {
             origin,
-            image_height : IMAGE_HEIGHT as f64,
-            image_width : IMAGE_WIDTH as f64,
+            image_height,
+            image_width,
+            samples_per_pixel: settings.samples_per_pixel,
+            max_depth: settings.max_depth,
+            threads: settings.threads,
+            tile_size: settings.tile_size,
+            seed: settings.seed,
+            background: settings.background,
+            auxiliary_layers: settings.auxiliary_layers,
             pixel_delta_v,
             pixel_delta_u,
             pixel_origin,
             defocus_disk_u,
             defocus_disk_v,
-            defocus_angle,
-            focus_dist,
-            lens_radius
-        }
+            defocus_angle: settings.defocus_angle
+        })
     }
 
-    pub fn render(&self, world : &dyn Hittable) {
-        let mut ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+    // Splits the image into tiles and hands them out to worker threads, which send each finished
+    // tile back to be copied into the framebuffer. Every pixel only depends on its own samples and
+    // each pixel draws its random numbers from its own seeded sampler, so the number of threads and
+    // the order tiles finish in don't change the image.
+    //
+    // Paths sample the lights directly wherever they hit a surface that isn't specular. Emissive
+    // objects left out of lights.area still glow, but paths only find them by chance, as in the
+    // book.
+    pub fn render(&self, world : &dyn Hittable, lights : &Lights) -> Framebuffer {
+        self.render_with_progress(world, lights, |_, _| {})
+    }
 
-        //create a file
-        let mut data_file = File::create("scene.ppm").expect("Creation failed.");
+    // Like render, calling progress with the number of tiles finished so far and the total each
+    // time one is copied in. It's called from the calling thread, in the order tiles finish.
+    pub fn render_with_progress(&self, world : &dyn Hittable, lights : &Lights, mut progress: impl FnMut(usize, usize)) -> Framebuffer {
+        let scene = PathScene { world, lights, background: &self.background };
+        let tiles = self.tiles();
+        let next_tile = AtomicUsize::new(0);
+        let threads = self.thread_count().min(tiles.len());
+        let mut framebuffer = Framebuffer::new(self.image_width, self.image_height);
+        if self.auxiliary_layers {
+            framebuffer.add_layer(NORMAL_LAYER, &["X", "Y", "Z"]);
+            framebuffer.add_layer(DEPTH_LAYER, &["Z"]);
+        }
 
-        //header required for ppm file
-        data_file.write("P3\n".as_bytes()).expect("write failed");
-        data_file.write((self.image_width.to_string() + " " + &*self.image_height.to_string() + "\n").as_bytes()).expect("write failed");
-        data_file.write("255\n".as_bytes()).expect("write failed");
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let tiles = &tiles;
+                let next_tile = &next_tile;
+                let scene = &scene;
+
+                scope.spawn(move || {
+                    loop {
+                        let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                        let Some(tile) = tiles.get(index) else { break };
+
+                        let pixels = self.render_tile(tile, scene);
+                        if sender.send((*tile, pixels)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
 
-        //generate pixel colours in matrix and add to ppm file
-        for i in (0..self.image_height as u32 +1).rev() {
-            //progress tracker
-            println!("On row {} of {}", self.image_height - i as f64, self.image_height);
+            // drop the original sender so the receiver finishes once every worker is done
+            drop(sender);
+
+            for (finished, (tile, pixels)) in receiver.iter().enumerate() {
+                let mut pixels = pixels.into_iter();
+                for j in tile.y0..tile.y1 {
+                    for i in tile.x0..tile.x1 {
+                        let pixel: RenderedPixel = pixels.next().unwrap();
+                        framebuffer.set(i, j, pixel.colour);
+
+                        if let Some((normal, depth)) = pixel.first_hit {
+                            let normal = [normal.x as f32, normal.y as f32, normal.z as f32];
+                            framebuffer.layer_mut(NORMAL_LAYER).unwrap().set(i, j, &normal);
+                            framebuffer.layer_mut(DEPTH_LAYER).unwrap().set(i, j, &[depth]);
+                        }
+                    }
+                }
+
+                progress(finished + 1, tiles.len());
+            }
+        });
+
+        framebuffer
+    }
+
+    // Renders the pixels of one tile, row by row
+    fn render_tile(&self, tile: &Tile, scene: &PathScene) -> Vec<RenderedPixel> {
+        let mut pixels = Vec::with_capacity(tile.pixel_count());
 
-            for j in 0..self.image_width as u32 {
+        for j in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                let mut sampler = Sampler::for_pixel(self.seed, i, j);
                 let mut pixel_colour = Colour::new(0.0, 0.0, 0.0);
 
-                for sample in 0..SAMPLES_PER_PIXEL {
-                    ray = self.get_ray(i, j);
-                    pixel_colour += ray_colour(&ray, MAX_DEPTH, world);
+                for _ in 0..self.samples_per_pixel {
+                    let ray = self.get_ray(i, j, &mut sampler);
+                    pixel_colour += ray_colour(&ray, self.max_depth, scene, None, &mut sampler);
                 }
 
-                let pixel_colour = pixel_colour.to_string(SAMPLES_PER_PIXEL);
+                pixels.push(RenderedPixel {
+                    colour: pixel_colour / self.samples_per_pixel as f64,
+                    first_hit: self.auxiliary_layers.then(|| self.first_hit(i, j, scene.world))
+                });
+            }
+        }
+
+        pixels
+    }
+
+    // Surface normal and distance to the first surface seen through the centre of a pixel, for the
+    // auxiliary layers. Pixels that only see the background get a zero normal and infinite depth.
+    fn first_hit(&self, i: u32, j: u32, world: &dyn Hittable) -> (Vector3, f32) {
+        let pixel_center = self.pixel_origin + (self.pixel_delta_u * i as f64) + (self.pixel_delta_v * j as f64);
+        let ray = Ray::new(self.origin, pixel_center - self.origin);
+
+        match world.hit(&ray, Interval::new(0.0, f64::INFINITY)) {
+            Some(record) => (record.normal, (record.point - self.origin).length() as f32),
+            None => (Vector3::new(0.0, 0.0, 0.0), f32::INFINITY)
+        }
+    }
 
-                data_file.write((pixel_colour).as_bytes()).expect("write failed");
+    fn tiles(&self) -> Vec<Tile> {
+        let mut tiles = vec![];
+
+        for y0 in (0..self.image_height).step_by(self.tile_size as usize) {
+            for x0 in (0..self.image_width).step_by(self.tile_size as usize) {
+                tiles.push(Tile {
+                    x0,
+                    y0,
+                    x1: (x0 + self.tile_size).min(self.image_width),
+                    y1: (y0 + self.tile_size).min(self.image_height)
+                });
             }
         }
 
-        println!("Created a file");
+        tiles
+    }
+
+    fn thread_count(&self) -> usize {
+        if self.threads > 0 {
+            return self.threads;
+        }
+
+        thread::available_parallelism().map_or(1, |count| count.get())
     }
 
     // Returns a random point in the square surrounding a pixel at the origin
-    fn pixel_sample_square(&self) -> Vector3 {
-        let px = util::random() - 0.5;
-        let py = util::random() - 0.5;
+    fn pixel_sample_square(&self, sampler: &mut Sampler) -> Vector3 {
+        let px = sampler.random() - 0.5;
+        let py = sampler.random() - 0.5;
 
-        return (self.pixel_delta_u * px) + (self.pixel_delta_v * py);
+        (self.pixel_delta_u * px) + (self.pixel_delta_v * py)
     }
 
-    //Get a randomly sampled camera ray for the pixel at location i,j
-    fn get_ray(&self, i: u32, j : u32) -> Ray {
+    //Get a randomly sampled camera ray for the pixel in column i, row j
+    fn get_ray(&self, i: u32, j : u32, sampler: &mut Sampler) -> Ray {
         // Get a randomly-sampled camera ray for the pixel at location i,j, originating from
         // the camera defocus disk.
 
         let pixel_center = self.pixel_origin + (self.pixel_delta_u * i as f64) + (self.pixel_delta_v * j as f64);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+        let pixel_sample = pixel_center + self.pixel_sample_square(sampler);
 
-        let ray_origin = if self.defocus_angle <= 0.0  {self.origin} else {self.defocus_disk_sample()};
+        let ray_origin = if self.defocus_angle <= 0.0  {self.origin} else {self.defocus_disk_sample(sampler)};
         let ray_direction = pixel_sample - ray_origin;
 
-        return Ray::new(ray_origin, ray_direction);
+        // The neighbouring rays go through the same lens point. They're a pixel over, moved in
+        // by the spacing between samples when there are several per pixel, so textures are only
+        // filtered as much as the samples can't resolve on their own.
+        let spacing = (1.0 / (self.samples_per_pixel as f64).sqrt()).max(0.125);
+        let differentials = RayDifferentials {
+            rx_origin: ray_origin,
+            rx_direction: pixel_sample + self.pixel_delta_u * spacing - ray_origin,
+            ry_origin: ray_origin,
+            ry_direction: pixel_sample + self.pixel_delta_v * spacing - ray_origin
+        };
+
+        Ray::with_differentials(ray_origin, ray_direction, differentials)
+    }
+
+    fn defocus_disk_sample(&self, sampler: &mut Sampler) -> Point3 {
+        let p = random_in_unit_disk(sampler);
+
+        self.origin + (self.defocus_disk_u * p.x) + (self.defocus_disk_v * p.y)
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = random_in_unit_disk();
+}
+
+// Everything rendered for a single pixel: its colour, and its normal and depth when the
+// auxiliary layers are on
+struct RenderedPixel {
+    colour: Colour,
+    first_hit: Option<(Vector3, f32)>
+}
+
+// A rectangular block of pixels, from (x0, y0) up to but not including (x1, y1)
+#[derive(Clone, Copy, Debug)]
+struct Tile {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32
+}
 
-        return self.origin + (self.defocus_disk_u * p.x) + (self.defocus_disk_v * p.y);
+impl Tile {
+    fn pixel_count(&self) -> usize {
+        ((self.x1 - self.x0) * (self.y1 - self.y0)) as usize
     }
+}
 
+// Everything a path needs from the scene while it bounces around
+struct PathScene<'a> {
+    world: &'a dyn Hittable,
+    lights: &'a Lights,
+    background: &'a Background
 }
 
-fn ray_colour(ray:&Ray, depth : i32,  world : &dyn Hittable) -> Colour {
+// When the previous bounce sampled the lights as well as its material, bsdf_pdf is the density its
+// material had of picking this ray. If the ray has hit one of the lights, the emission is weighed
+// against the chance light sampling had of finding the same point (multiple importance sampling).
+// Camera rays and rays from specular bounces have no alternative, so they count emission in full.
+fn ray_colour(ray:&Ray, depth : u32, scene : &PathScene, bsdf_pdf : Option<f64>, sampler : &mut Sampler) -> Colour {
 
     //ensure function doesn't recurse forever (stop gathering light if at max depth)
-    if depth <= 0 {
+    if depth == 0 {
         return Colour::new(0.0, 0.0, 0.0);
     }
 
-    //calculate hit point and colour sphere according to its normal vectors
     //ignore hits very close to the calculated intersection point (range starts at 0.001) for the shadow acne
-    if let Some(record) = world.hit(ray, Interval::new(0.001, f64::INFINITY)) {
+    let Some(mut record) = scene.world.hit(ray, Interval::new(0.001, f64::INFINITY)) else {
+        //this is anything not hitting a shape
+        return scene.background.colour(ray);
+    };
+    record.compute_differentials(ray);
+
+    let mut emitted = record.material.emitted(ray, &record);
+    if let Some(bsdf_pdf) = bsdf_pdf {
+        if hits_light(&scene.lights.area, ray, record.t) {
+            let light_pdf = scene.lights.area.pdf_value(ray.origin, ray.direction);
+            emitted = emitted * power_heuristic(bsdf_pdf, light_pdf);
+        }
+    }
 
-        return if let Some((attenuation, scattered)) = record.material.scatter(ray, &record) {
-            attenuation * ray_colour(&scattered, depth - 1, world)
-        } else {
-            Colour::new(0.0, 0.0, 0.0)
+    if record.material.is_specular() {
+        return match record.material.scatter(ray, &record, sampler) {
+            Some((attenuation, scattered)) => emitted + attenuation * ray_colour(&scattered, depth - 1, scene, None, sampler),
+            None => emitted
         };
     }
 
-    let unit_direction : Vector3 = Vector3::unit(ray.direction);
-    let a = 0.5 * (unit_direction.y + 1.0);
+    // The light samples stand on their own, even when scatter absorbs the ray. On the last bounce
+    // the scattered ray can't find a light any more, so the area lights are left out there too,
+    // or they'd only get their half of the weights.
+    let area = if depth > 1 { direct_light(ray, &record, scene, sampler) } else { Colour::new(0.0, 0.0, 0.0) };
+    let direct = area + analytic_light(ray, &record, scene);
+
+    let Some((attenuation, scattered)) = record.material.scatter(ray, &record, sampler) else {
+        return emitted + direct;
+    };
+
+    let bsdf_pdf = record.material.scattering_pdf(ray, &record, scattered.direction);
+
+    emitted + direct + attenuation * ray_colour(&scattered, depth - 1, scene, Some(bsdf_pdf), sampler)
+}
+
+// Next event estimation: light reaching a surface straight from a light, estimated with a shadow
+// ray towards a point picked on one of the lights and weighed against the chance the material had
+// of scattering the same way
+fn direct_light(ray: &Ray, record: &HitRecord, scene: &PathScene, sampler: &mut Sampler) -> Colour {
+    let black = Colour::new(0.0, 0.0, 0.0);
+
+    let direction = scene.lights.area.random(record.point, sampler);
+    let light_pdf = scene.lights.area.pdf_value(record.point, direction);
+    if light_pdf <= 0.0 {
+        return black;
+    }
+
+    let bsdf = record.material.eval(ray, record, direction);
+    if bsdf.near_zero() {
+        return black;
+    }
+
+    // the light only counts if it's the first thing the shadow ray hits
+    let shadow_ray = Ray::new(record.point, direction);
+    match scene.world.hit(&shadow_ray, Interval::new(0.001, f64::INFINITY)) {
+        Some(light) if hits_light(&scene.lights.area, &shadow_ray, light.t) => {
+            let emitted = light.material.emitted(&shadow_ray, &light);
+            let bsdf_pdf = record.material.scattering_pdf(ray, record, direction);
+
+            bsdf * emitted * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+        }
+        _ => black
+    }
+}
+
+// Light from every point, spot and directional light that can see the surface. Nothing else can
+// find these lights, so there's nothing to weigh them against.
+fn analytic_light(ray: &Ray, record: &HitRecord, scene: &PathScene) -> Colour {
+    let mut total = Colour::new(0.0, 0.0, 0.0);
+
+    for light in &scene.lights.analytic {
+        let Some(sample) = light.sample(record.point) else { continue };
+
+        let bsdf = record.material.eval(ray, record, sample.direction);
+        if bsdf.near_zero() {
+            continue;
+        }
+
+        let shadow_ray = Ray::new(record.point, sample.direction);
+        if !scene.world.any_hit(&shadow_ray, Interval::new(0.001, sample.distance - 0.001)) {
+            total += bsdf * sample.radiance;
+        }
+    }
+
+    total
+}
+
+// Weight for a sample from a strategy with density pdf when another strategy could have produced
+// it with density other_pdf (Veach's power heuristic with an exponent of 2)
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        return 0.0;
+    }
+
+    a / (a + b)
+}
 
-    //this is anything not hitting a shape: a white-to blue gradient background
-    return Colour::new(1.0, 1.0, 1.0) * (1.0 - a) + Colour::new(0.5, 0.7, 1.0) * a;
-}
\ No newline at end of file
+// whether the surface the ray hit at t belongs to one of the lights
+fn hits_light(lights: &dyn Hittable, ray: &Ray, t: f64) -> bool {
+    let tolerance = 1e-9 * t.max(1.0);
+    lights.any_hit(ray, Interval::new(t - tolerance, t + tolerance))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use std::path::Path;
+    use std::sync::Arc;
+    use crate::camera::{analytic_light, power_heuristic, Background, CameraBuilder, CameraError, PathScene, DEPTH_LAYER};
+    use crate::hittable::HitRecord;
+    use crate::hittable_list::HittableList;
+    use crate::light::{DirectionalLight, Lights};
+    use crate::material::Matte;
+    use crate::ray::Ray;
+    use crate::scene::builtin::cornell_box;
+    use crate::scene::file::parse_scene;
+    use crate::sphere::Sphere;
+    use crate::vector3::{Colour, Point3, Vector3};
+
+    fn error(builder: CameraBuilder) -> CameraError {
+        match builder.build() {
+            Err(error) => error,
+            Ok(_) => panic!("{:?} should have been rejected", builder.settings())
+        }
+    }
+
+    #[test]
+    fn rejects_zero_width() {
+        assert_eq!(error(CameraBuilder::new().image_width(0)), CameraError::ZeroImageWidth);
+    }
+
+    #[test]
+    fn rejects_aspect_ratios_without_a_row_of_pixels() {
+        for ratio in [0.0, -1.5, f64::NAN, f64::INFINITY] {
+            assert!(matches!(error(CameraBuilder::new().aspect_ratio(ratio)), CameraError::InvalidAspectRatio(_)), "{}", ratio);
+        }
+
+        // valid on its own, but 100 pixels wide makes less than one row
+        let builder = CameraBuilder::new().image_width(100).aspect_ratio(150.0);
+        assert_eq!(error(builder), CameraError::InvalidAspectRatio(150.0));
+    }
+
+    #[test]
+    fn rejects_zero_samples() {
+        assert_eq!(error(CameraBuilder::new().samples_per_pixel(0)), CameraError::ZeroSamplesPerPixel);
+    }
+
+    #[test]
+    fn rejects_non_positive_focus_distances() {
+        for distance in [0.0, -2.0, f64::NAN] {
+            assert!(matches!(error(CameraBuilder::new().focus_dist(distance)), CameraError::InvalidFocusDistance(_)), "{}", distance);
+        }
+    }
+
+    #[test]
+    fn rejects_zero_tile_size() {
+        assert_eq!(error(CameraBuilder::new().tile_size(0)), CameraError::ZeroTileSize);
+    }
+
+    #[test]
+    fn rejects_fields_of_view_out_of_range() {
+        for vfov in [0.0, -30.0, 180.0, 270.0, f64::NAN] {
+            assert!(matches!(error(CameraBuilder::new().vfov(vfov)), CameraError::InvalidFieldOfView(_)), "{}", vfov);
+        }
+    }
+
+    #[test]
+    fn rejects_looking_at_the_camera_position() {
+        let point = Point3::new(1.0, 2.0, 3.0);
+        assert_eq!(error(CameraBuilder::new().look_from(point).look_at(point)), CameraError::DegenerateView);
+    }
+
+    #[test]
+    fn rejects_up_along_the_view_direction() {
+        let builder = CameraBuilder::new()
+            .look_from(Point3::new(0.0, 5.0, 0.0))
+            .look_at(Point3::new(0.0, 0.0, 0.0))
+            .vup(Vector3::new(0.0, -2.0, 0.0));
+        assert_eq!(error(builder), CameraError::DegenerateView);
+    }
+
+    #[test]
+    fn builder_keeps_what_it_was_given() {
+        let builder = CameraBuilder::new()
+            .aspect_ratio(2.0)
+            .image_width(300)
+            .samples_per_pixel(7)
+            .max_depth(9)
+            .vfov(35.0)
+            .look_from(Point3::new(1.0, 2.0, 3.0))
+            .look_at(Point3::new(-1.0, 0.0, 0.5))
+            .vup(Vector3::new(0.0, 0.0, 1.0))
+            .defocus_angle(1.5)
+            .focus_dist(4.0)
+            .threads(3)
+            .tile_size(8)
+            .seed(42)
+            .background(Background::Solid(Colour::new(0.1, 0.2, 0.3)))
+            .auxiliary_layers(true);
+        assert!(builder.build().is_ok());
+
+        let settings = builder.settings();
+        assert_eq!((settings.aspect_ratio, settings.image_width, settings.image_height()), (2.0, 300, 150));
+        assert_eq!((settings.samples_per_pixel, settings.max_depth, settings.vfov), (7, 9, 35.0));
+        assert_eq!((settings.look_from.x, settings.look_from.y, settings.look_from.z), (1.0, 2.0, 3.0));
+        assert_eq!((settings.look_at.x, settings.look_at.y, settings.look_at.z), (-1.0, 0.0, 0.5));
+        assert_eq!((settings.vup.x, settings.vup.y, settings.vup.z), (0.0, 0.0, 1.0));
+        assert_eq!((settings.defocus_angle, settings.focus_dist), (1.5, 4.0));
+        assert_eq!((settings.threads, settings.tile_size, settings.seed, settings.auxiliary_layers), (3, 8, 42, true));
+        assert!(matches!(settings.background, Background::Solid(colour) if colour.z == 0.3));
+
+        // and the defaults are valid as they are
+        assert!(CameraBuilder::new().build().is_ok());
+    }
+
+    // a few tiles of the Cornell box, which has a light for the paths to sample directly
+    fn render(threads: usize) -> (Vec<[u64; 3]>, Vec<u32>) {
+        let scene = cornell_box();
+        let camera = CameraBuilder::from_settings(scene.camera)
+            .image_width(24)
+            .samples_per_pixel(4)
+            .tile_size(5)
+            .threads(threads)
+            .auxiliary_layers(true)
+            .build()
+            .unwrap();
+
+        let framebuffer = camera.render(&scene.world, &scene.lights);
+
+        // compared bit for bit, so even the last rounding error has to match
+        let colours = framebuffer.pixels().iter().map(|colour| [colour.x.to_bits(), colour.y.to_bits(), colour.z.to_bits()]).collect();
+        let depths = framebuffer.layer(DEPTH_LAYER).unwrap().channel(0).map(f32::to_bits).collect();
+
+        (colours, depths)
+    }
+
+    #[test]
+    fn image_does_not_depend_on_thread_count() {
+        let single = render(1);
+
+        assert_eq!(single, render(4));
+        assert!(single.0.iter().any(|colour| colour.iter().any(|&bits| bits != 0)), "the render came out black");
+    }
+
+    #[test]
+    fn progress_counts_every_tile_once() {
+        let scene = cornell_box();
+        let camera = CameraBuilder::from_settings(scene.camera)
+            .image_width(24)
+            .samples_per_pixel(1)
+            .tile_size(5)
+            .threads(3)
+            .build()
+            .unwrap();
+
+        let mut calls = vec![];
+        camera.render_with_progress(&scene.world, &scene.lights, |finished, total| calls.push((finished, total)));
+
+        // 24 by 24 pixels in tiles of 5 is 5 tiles across and 5 down
+        assert_eq!(calls, (1..=25).map(|finished| (finished, 25)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn power_heuristic_weights_sum_to_one() {
+        for (a, b) in [(1.0, 1.0), (0.3, 2.0), (5.0, 0.01), (0.0, 4.0)] {
+            assert!((power_heuristic(a, b) + power_heuristic(b, a) - 1.0).abs() < 1e-12);
+        }
+
+        assert_eq!(power_heuristic(1.0, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+    }
+
+    // Sampling the light as well as the materials only changes the noise, not the brightness the
+    // image converges to. Two bounces is just the light reaching the first surfaces, where material
+    // sampling alone converges quickly enough to compare against.
+    #[test]
+    fn light_sampling_matches_material_sampling() {
+        // the boxes version, as the glass sphere's caustics are noisy either way
+        let scene = parse_scene(include_str!("../scenes/cornell_blocks.toml"), Path::new("cornell_blocks.toml")).unwrap();
+        let camera = CameraBuilder::from_settings(scene.camera)
+            .image_width(12)
+            .samples_per_pixel(1600)
+            .max_depth(2)
+            .build()
+            .unwrap();
+
+        let mean = |lights: &Lights| {
+            let framebuffer = camera.render(&scene.world, lights);
+            let pixels = framebuffer.pixels();
+            pixels.iter().map(|colour| colour.x + colour.y + colour.z).sum::<f64>() / (3 * pixels.len()) as f64
+        };
+
+        // with no lights to sample, paths only find the light by scattering into it
+        let mis = mean(&scene.lights);
+        let bsdf_only = mean(&Lights::new());
+
+        assert!((mis - bsdf_only).abs() < 0.05 * bsdf_only, "{} against {}", mis, bsdf_only);
+    }
+
+    #[test]
+    fn directional_lights_cast_shadows_at_any_distance() {
+        let material = Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(0.0, 3.0, 0.0), 1.0, material.clone())));
+        let mut lights = Lights::new();
+        lights.add(Box::new(DirectionalLight::new(Vector3::new(0.0, -1.0, 0.0), Colour::new(2.0, 2.0, 2.0))));
+        let background = Background::Solid(Colour::new(0.0, 0.0, 0.0));
+        let scene = PathScene { world: &world, lights: &lights, background: &background };
+
+        // the light on a floor facing up, under the sphere or away from it
+        let light_at = |x: f64| {
+            let ray = Ray::new(Point3::new(x, 1.0, 1.0), Vector3::new(0.0, -1.0, -1.0));
+            let record = HitRecord::new(Point3::new(x, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), 1.0, material.clone());
+            analytic_light(&ray, &record, &scene).x
+        };
+
+        assert_eq!(light_at(0.0), 0.0);
+        assert_eq!(light_at(0.5), 0.0);
+        assert!((light_at(5.0) - 2.0 * 0.5 / PI).abs() < 1e-12);
+        assert_eq!(light_at(5.0), light_at(5000.0));
+    }
+}
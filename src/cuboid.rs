@@ -0,0 +1,330 @@
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::triangle::area_pdf;
+use crate::vector3::*;
+
+// A rectangular box, as one closed solid rather than six separate faces, so it can be made of
+// glass and reports both of a ray's crossings to all_hits. It's made from two opposite corners,
+// with its faces lined up with the axes, and can then be rotated about its centre. As a light
+// it's sampled uniformly over its whole surface.
+//
+// Each face's surface coordinates run from 0 to 1 across it. On the faces perpendicular to an
+// axis, u goes along the next axis round (x to y to z to x) and v along the one after.
+pub struct Cuboid {
+    center: Point3,
+    half_size: Vector3,
+    axes: [Vector3; 3], // where the box's own x, y and z axes point after rotation
+    material: Arc<dyn Material>,
+    bbox: Aabb
+}
+
+// where a ray's line crosses the surface: at t, through the face perpendicular to axis on the
+// given side (-1 or 1) of the box
+#[derive(Clone, Copy)]
+struct Crossing {
+    t: f64,
+    axis: usize,
+    side: f64
+}
+
+impl Cuboid {
+    pub fn new(a: Point3, b: Point3, material: Arc<dyn Material>) -> Cuboid {
+        let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+        Cuboid {
+            center: (min + max) * 0.5,
+            half_size: (max - min) * 0.5,
+            axes: [Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0)],
+            material,
+            bbox: Aabb::from_points(min, max)
+        }
+    }
+
+    // The same box turned by angle degrees about axis through its centre, counter-clockwise as
+    // seen with the axis pointing at the viewer
+    pub fn rotated(a: Point3, b: Point3, axis: Vector3, angle: f64, material: Arc<dyn Material>) -> Cuboid {
+        let mut cuboid = Cuboid::new(a, b, material);
+        let axis = axis.unit();
+        let (sin, cos) = angle.to_radians().sin_cos();
+
+        // Rodrigues' rotation formula
+        let rotate = |v: Vector3| v * cos + axis.cross(v) * sin + axis * (axis.dot(v) * (1.0 - cos));
+        cuboid.axes = cuboid.axes.map(rotate);
+
+        // the box around all eight corners
+        let mut bbox = Aabb::EMPTY;
+        for corner in 0..8 {
+            let sign = |bit: usize| if corner & (1 << bit) == 0 { -1.0 } else { 1.0 };
+            let point = cuboid.to_world(Vector3::new(sign(0) * cuboid.half_size.x, sign(1) * cuboid.half_size.y, sign(2) * cuboid.half_size.z));
+            bbox = Aabb::union(bbox, Aabb::from_points(point, point));
+        }
+        cuboid.bbox = bbox;
+
+        cuboid
+    }
+
+    // the area of each face perpendicular to axis
+    fn face_area(&self, axis: usize) -> f64 {
+        4.0 * self.half_size[(axis + 1) % 3] * self.half_size[(axis + 2) % 3]
+    }
+
+    fn area(&self) -> f64 {
+        2.0 * (0..3).map(|axis| self.face_area(axis)).sum::<f64>()
+    }
+
+    fn to_world(&self, local: Vector3) -> Point3 {
+        self.center + self.axes[0] * local.x + self.axes[1] * local.y + self.axes[2] * local.z
+    }
+
+    fn to_local(&self, v: Vector3) -> Vector3 {
+        Vector3::new(v.dot(self.axes[0]), v.dot(self.axes[1]), v.dot(self.axes[2]))
+    }
+
+    // Slab test in the box's own space, where it's axis aligned and centred on the origin. Gives
+    // where the ray's line enters and leaves the box.
+    fn crossings(&self, ray: &Ray) -> Option<(Crossing, Crossing)> {
+        let origin = self.to_local(ray.origin - self.center);
+        let direction = self.to_local(ray.direction);
+
+        let mut enter = Crossing { t: f64::NEG_INFINITY, axis: 0, side: 0.0 };
+        let mut leave = Crossing { t: f64::INFINITY, axis: 0, side: 0.0 };
+
+        for axis in 0..3 {
+            let half = self.half_size[axis];
+            if direction[axis] == 0.0 {
+                // parallel to this pair of faces, so never crossing them
+                if origin[axis].abs() > half {
+                    return None;
+                }
+                continue;
+            }
+
+            // the face the ray comes in through is on the side it's coming from
+            let side = if direction[axis] > 0.0 { -1.0 } else { 1.0 };
+            let t_in = (side * half - origin[axis]) / direction[axis];
+            let t_out = (-side * half - origin[axis]) / direction[axis];
+
+            if t_in > enter.t {
+                enter = Crossing { t: t_in, axis, side };
+            }
+            if t_out < leave.t {
+                leave = Crossing { t: t_out, axis, side: -side };
+            }
+        }
+
+        if enter.t > leave.t {
+            return None;
+        }
+
+        Some((enter, leave))
+    }
+
+    fn record(&self, ray: &Ray, crossing: Crossing) -> HitRecord {
+        let Crossing { t, axis, side } = crossing;
+        let point = ray.at(t);
+        let outward_normal = self.axes[axis] * side;
+
+        let mut record = HitRecord::new(point, outward_normal, t, Arc::clone(&self.material));
+        record.set_face_normal(ray, outward_normal);
+
+        // the face spans -half to half along the other two axes
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let local = self.to_local(point - self.center);
+        record.u = 0.5 + local[u_axis] / (2.0 * self.half_size[u_axis]);
+        record.v = 0.5 + local[v_axis] / (2.0 * self.half_size[v_axis]);
+
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        record.set_derivatives(
+            self.axes[u_axis] * (2.0 * self.half_size[u_axis]),
+            self.axes[v_axis] * (2.0 * self.half_size[v_axis]),
+            zero,
+            zero
+        );
+
+        record
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let (enter, leave) = self.crossings(ray)?;
+
+        // from inside the box only the way out is ahead
+        for crossing in [enter, leave] {
+            if ray_t.surrounds(crossing.t) {
+                return Some(self.record(ray, crossing));
+            }
+        }
+
+        None
+    }
+
+    fn collect_hits(&self, ray: &Ray, ray_t: Interval, hits: &mut Vec<HitRecord>) {
+        if let Some((enter, leave)) = self.crossings(ray) {
+            for crossing in [enter, leave] {
+                if ray_t.surrounds(crossing.t) {
+                    hits.push(self.record(ray, crossing));
+                }
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // A direction can cross the surface twice, on the way in and out, and random could have
+    // picked either point
+    fn pdf_value(&self, origin: Point3, direction: Vector3) -> f64 {
+        let ray = Ray::new(origin, direction);
+        let Some((enter, leave)) = self.crossings(&ray) else {
+            return 0.0;
+        };
+
+        let area = self.area();
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+
+        [enter, leave].iter()
+            .filter(|crossing| ray_t.surrounds(crossing.t))
+            .map(|crossing| area_pdf(&ray, crossing.t, self.axes[crossing.axis], area))
+            .sum()
+    }
+
+    // picks a face in proportion to its area, then a point evenly over it
+    fn random(&self, origin: Point3, sampler: &mut Sampler) -> Vector3 {
+        let mut target = sampler.random() * self.area();
+        let mut face = 5;
+        for index in 0..6 {
+            target -= self.face_area(index / 2);
+            if target < 0.0 {
+                face = index;
+                break;
+            }
+        }
+
+        let axis = face / 2;
+        let side = if face % 2 == 0 { -1.0 } else { 1.0 };
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        let mut local = [0.0; 3];
+        local[axis] = side * self.half_size[axis];
+        local[u_axis] = (2.0 * sampler.random() - 1.0) * self.half_size[u_axis];
+        local[v_axis] = (2.0 * sampler.random() - 1.0) * self.half_size[v_axis];
+
+        self.to_world(Vector3::new(local[0], local[1], local[2])) - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::cuboid::Cuboid;
+    use crate::hittable::Hittable;
+    use crate::hittable::test_support::{pdf_integral, solid_angles};
+    use crate::interval::Interval;
+    use crate::material::{Material, Matte};
+    use crate::ray::Ray;
+    use crate::vector3::{Colour, Point3, Vector3};
+
+    const FORWARD: Interval = Interval { min: 0.001, max: f64::INFINITY };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)))
+    }
+
+    // 2 by 4 by 6, with a corner at the origin
+    fn cuboid() -> Cuboid {
+        Cuboid::new(Point3::new(2.0, 4.0, 6.0), Point3::new(0.0, 0.0, 0.0), material())
+    }
+
+    #[test]
+    fn axis_aligned_hits_give_the_face_normal_and_coordinates() {
+        // onto the +x face, where u runs along y and v along z
+        let ray = Ray::new(Point3::new(5.0, 1.0, 4.5), Vector3::new(-1.0, 0.0, 0.0));
+        let record = cuboid().hit(&ray, FORWARD).unwrap();
+
+        assert_eq!(record.t, 3.0);
+        assert!(record.front_face);
+        assert_eq!((record.normal.x, record.normal.y, record.normal.z), (1.0, 0.0, 0.0));
+        assert_eq!((record.u, record.v), (0.25, 0.75));
+
+        // onto the -z face, where u runs along x and v along y
+        let ray = Ray::new(Point3::new(0.5, 3.0, -1.0), Vector3::new(0.0, 0.0, 1.0));
+        let record = cuboid().hit(&ray, FORWARD).unwrap();
+
+        assert_eq!((record.normal.x, record.normal.y, record.normal.z), (0.0, 0.0, -1.0));
+        assert_eq!((record.u, record.v), (0.25, 0.75));
+    }
+
+    #[test]
+    fn rays_from_inside_only_hit_the_way_out() {
+        let ray = Ray::new(Point3::new(1.0, 2.0, 3.0), Vector3::new(0.0, 0.0, 1.0));
+
+        let record = cuboid().hit(&ray, FORWARD).unwrap();
+        assert_eq!(record.t, 3.0);
+        assert!(!record.front_face);
+        assert_eq!(record.normal.z, -1.0);
+
+        assert_eq!(cuboid().all_hits(&ray, FORWARD).len(), 1);
+    }
+
+    #[test]
+    fn all_hits_gives_the_way_in_and_out_in_order() {
+        let ray = Ray::new(Point3::new(-1.0, 1.0, 1.0), Vector3::new(1.0, 0.5, 0.0));
+        let hits = cuboid().all_hits(&ray, FORWARD);
+
+        assert_eq!(hits.iter().map(|record| record.t).collect::<Vec<_>>(), [1.0, 3.0]);
+        assert!(hits[0].front_face && !hits[1].front_face);
+        assert_eq!(hits[0].normal.x, -1.0);
+        assert_eq!(hits[1].normal.x, -1.0);
+
+        // missing the box altogether
+        let ray = Ray::new(Point3::new(-1.0, 5.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(cuboid().all_hits(&ray, FORWARD).is_empty());
+    }
+
+    #[test]
+    fn rotated_boxes_fit_their_corners() {
+        let axis = Vector3::new(0.0, 1.0, 0.0);
+        let cuboid = Cuboid::rotated(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 4.0, 6.0), axis, 45.0, material());
+        let bbox = cuboid.bounding_box();
+
+        // the corners turned 45 degrees about the vertical line through the centre (1, 2, 3)
+        let (sin, cos) = 45f64.to_radians().sin_cos();
+        for x in [0.0, 2.0] {
+            for y in [0.0, 4.0] {
+                for z in [0.0, 6.0] {
+                    let (dx, dz) = (x - 1.0, z - 3.0);
+                    let corner = Point3::new(1.0 + dx * cos + dz * sin, y, 3.0 - dx * sin + dz * cos);
+
+                    assert!(bbox.x.contains(corner.x) && bbox.y.contains(corner.y) && bbox.z.contains(corner.z));
+                }
+            }
+        }
+
+        // and nothing much more: the widest corners reach (1 + 3) / sqrt(2) from the centre, and
+        // the box only gets the padding every corner's own box has
+        let reach = 4.0 / 2f64.sqrt();
+        assert!((bbox.x.max - (1.0 + reach)).abs() < 1e-4 && (bbox.z.min - (3.0 - reach)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn sampling_matches_its_density() {
+        let cuboid = Cuboid::rotated(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 4.0, 6.0), Vector3::new(1.0, 1.0, 0.0), 30.0, material());
+
+        // directions through the box cross it twice, and the density counts both points
+        for origin in [Point3::new(5.0, 2.0, 3.0), Point3::new(1.0, 2.0, 10.0), Point3::new(6.0, 6.0, 6.0)] {
+            let integral = pdf_integral(&cuboid, origin);
+            assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+
+            let (uniform, sampled) = solid_angles(&cuboid, origin);
+            assert!((uniform - sampled).abs() < 0.03 * uniform, "{} against {}", uniform, sampled);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::plane::intersect_plane;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::triangle::area_pdf;
+use crate::vector3::*;
+
+// A flat circle facing along normal. u is the angle around the centre as a fraction of a turn
+// and v the distance from the centre as a fraction of the radius.
+pub struct Disk {
+    center: Point3,
+    radius: f64,
+    frame: Onb,
+    material: Arc<dyn Material>,
+    bbox: Aabb
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vector3, radius: f64, material: Arc<dyn Material>) -> Disk {
+        let frame = Onb::new(normal);
+        let n = frame.w;
+
+        // along each axis the rim reaches radius times the sine of the angle between the axis
+        // and the normal
+        let extent = Vector3::new(
+            (1.0 - n.x * n.x).max(0.0).sqrt(),
+            (1.0 - n.y * n.y).max(0.0).sqrt(),
+            (1.0 - n.z * n.z).max(0.0).sqrt()
+        ) * radius;
+
+        Disk {
+            center,
+            radius,
+            frame,
+            material,
+            bbox: Aabb::from_points(center - extent, center + extent)
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let normal = self.frame.w;
+        let t = intersect_plane(ray, self.center, normal, ray_t)?;
+        let point = ray.at(t);
+
+        let offset = point - self.center;
+        let (x, y) = (offset.dot(self.frame.u), offset.dot(self.frame.v));
+        let distance = x.hypot(y);
+        if distance > self.radius {
+            return None;
+        }
+
+        let mut record = HitRecord::new(point, normal, t, Arc::clone(&self.material));
+        record.set_face_normal(ray, normal);
+
+        let phi = y.atan2(x).rem_euclid(2.0 * PI);
+        record.u = phi / (2.0 * PI);
+        record.v = distance / self.radius;
+
+        // the point is centre + r (cos phi frame.u + sin phi frame.v), with phi = 2 pi u and
+        // r = radius v
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let dpdu = (self.frame.v * cos_phi - self.frame.u * sin_phi) * (2.0 * PI * distance);
+        let dpdv = (self.frame.u * cos_phi + self.frame.v * sin_phi) * self.radius;
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        record.set_derivatives(dpdu, dpdv, zero, zero);
+
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // sampled uniformly by area
+    fn pdf_value(&self, origin: Point3, direction: Vector3) -> f64 {
+        let ray = Ray::new(origin, direction);
+        match self.hit(&ray, Interval::new(0.001, f64::INFINITY)) {
+            Some(record) => area_pdf(&ray, record.t, record.normal, PI * self.radius * self.radius),
+            None => 0.0
+        }
+    }
+
+    // the square root spreads points evenly, rather than bunching them at the centre
+    fn random(&self, origin: Point3, sampler: &mut Sampler) -> Vector3 {
+        let r = self.radius * sampler.random().sqrt();
+        let phi = 2.0 * PI * sampler.random();
+        let point = self.center + self.frame.u * (r * phi.cos()) + self.frame.v * (r * phi.sin());
+
+        point - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::disk::Disk;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::hittable::test_support::{pdf_integral, solid_angles};
+    use crate::interval::Interval;
+    use crate::material::Matte;
+    use crate::ray::Ray;
+    use crate::vector3::{Colour, Point3, Vector3};
+
+    // radius 2 around the origin, facing +z
+    fn disk() -> Disk {
+        let material = Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)));
+        Disk::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0), 2.0, material)
+    }
+
+    // where a ray straight down onto (x, y) hits, if it does
+    fn hit_at(x: f64, y: f64) -> Option<HitRecord> {
+        let ray = Ray::new(Point3::new(x, y, 1.0), Vector3::new(0.0, 0.0, -1.0));
+        disk().hit(&ray, Interval::new(0.001, f64::INFINITY))
+    }
+
+    #[test]
+    fn hits_inside_and_on_the_rim_only() {
+        for (x, y) in [(0.0, 0.0), (0.5, -1.0), (2.0, 0.0), (0.0, -2.0), (1.2, 1.6)] {
+            assert!(hit_at(x, y).is_some(), "({}, {})", x, y);
+        }
+        for (x, y) in [(2.01, 0.0), (1.5, 1.5), (0.0, -2.01)] {
+            assert!(hit_at(x, y).is_none(), "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn coordinates_run_around_and_out_from_the_centre() {
+        assert_eq!(hit_at(0.0, 0.0).unwrap().v, 0.0);
+        assert_eq!(hit_at(0.5, 0.0).unwrap().v, 0.25);
+
+        // a quarter turn apart on the rim, whichever way the disk's frame happens to point
+        let first = hit_at(2.0, 0.0).unwrap();
+        let second = hit_at(0.0, 2.0).unwrap();
+        assert!((first.v - 1.0).abs() < 1e-12 && (second.v - 1.0).abs() < 1e-12);
+        let turn = (second.u - first.u).rem_euclid(1.0);
+        assert!((turn - 0.25).abs() < 1e-12 || (turn - 0.75).abs() < 1e-12, "{}", turn);
+        assert!((0.0..1.0).contains(&first.u) && (0.0..1.0).contains(&second.u));
+    }
+
+    #[test]
+    fn sampling_matches_its_density() {
+        let disk = disk();
+
+        for origin in [Point3::new(0.0, 0.0, 1.0), Point3::new(2.5, -1.0, 1.0)] {
+            let (uniform, sampled) = solid_angles(&disk, origin);
+            assert!((uniform - sampled).abs() < 0.03 * uniform, "{} against {}", uniform, sampled);
+
+            let integral = pdf_integral(&disk, origin);
+            assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+        }
+    }
+}
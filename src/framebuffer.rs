@@ -0,0 +1,112 @@
+use crate::vector3::Colour;
+
+// In-memory image the renderer fills in before anything gets written out. Pixels are stored
+// row by row, starting at the top left, as the average of all the samples taken for them. Values
+// are linear RGB and aren't clamped: gamma correction and quantisation are left to the writers.
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Colour>,
+    layers: Vec<Layer>
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Colour::new(0.0, 0.0, 0.0); width as usize * height as usize],
+            layers: vec![]
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Colour {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, colour: Colour) {
+        let index = self.index(x, y);
+        self.pixels[index] = colour;
+    }
+
+    pub fn pixels(&self) -> &[Colour] {
+        &self.pixels
+    }
+
+    // Adds an auxiliary layer the same size as the image, filled with zeroes
+    pub fn add_layer(&mut self, name: &str, channels: &[&str]) -> &mut Layer {
+        let values = vec![0.0; self.pixels.len() * channels.len()];
+
+        self.layers.push(Layer {
+            name: name.to_string(),
+            channels: channels.iter().map(|channel| channel.to_string()).collect(),
+            width: self.width,
+            values
+        });
+
+        self.layers.last_mut().unwrap()
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+// Extra per-pixel data produced alongside the beauty image, such as surface normals or depth.
+// Each pixel has one value per channel, stored interleaved in the same row order as the image.
+pub struct Layer {
+    name: String,
+    channels: Vec<String>,
+    width: u32,
+    values: Vec<f32>
+}
+
+impl Layer {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> &[f32] {
+        let index = self.index(x, y);
+        &self.values[index..index + self.channels.len()]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, values: &[f32]) {
+        let index = self.index(x, y);
+        let channel_count = self.channels.len();
+        self.values[index..index + channel_count].copy_from_slice(values);
+    }
+
+    // all values of a single channel, row by row
+    pub fn channel(&self, channel: usize) -> impl Iterator<Item = f32> + '_ {
+        self.values.iter().skip(channel).step_by(self.channels.len()).copied()
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.width as usize + x as usize) * self.channels.len()
+    }
+}
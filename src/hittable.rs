@@ -1,6 +1,8 @@
 use std::sync::Arc;
+use crate::aabb::Aabb;
 use crate::interval::Interval;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::material::*;
 use crate::vector3::*;
 
@@ -10,7 +12,28 @@ pub struct HitRecord {
     pub normal : Vector3,
     pub material : Arc<dyn Material>,
     pub t : f64,
-    pub front_face : bool
+    pub front_face : bool,
+    pub barycentric : (f64, f64), // weights of a triangle's second and third vertices at the hit
+    pub u : f64, // surface (texture) coordinates of the hit
+    pub v : f64,
+    pub vertex_colour : Option<Colour>, // interpolated from meshes that have per-vertex colours
+    pub dpdu : Vector3, // how the point and normal change with u and v, zero if the shape doesn't say
+    pub dpdv : Vector3,
+    pub dndu : Vector3,
+    pub dndv : Vector3,
+    pub differentials : Option<SurfaceDifferentials> // for rays that carry differentials
+}
+
+// The patch of surface a ray with differentials covers: how the hit point and its surface
+// coordinates change between the ray and its x and y neighbours
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceDifferentials {
+    pub dpdx : Vector3,
+    pub dpdy : Vector3,
+    pub dudx : f64,
+    pub dvdx : f64,
+    pub dudy : f64,
+    pub dvdy : f64
 }
 
 impl HitRecord {
@@ -20,7 +43,16 @@ impl HitRecord {
             normal,
             t,
             front_face: false,
-            material
+            material,
+            barycentric: (0.0, 0.0),
+            u: 0.0,
+            v: 0.0,
+            vertex_colour: None,
+            dpdu: Vector3::new(0.0, 0.0, 0.0),
+            dpdv: Vector3::new(0.0, 0.0, 0.0),
+            dndu: Vector3::new(0.0, 0.0, 0.0),
+            dndv: Vector3::new(0.0, 0.0, 0.0),
+            differentials: None
         }
     }
 
@@ -35,8 +67,141 @@ impl HitRecord {
             -outward_normal
         }
     }
+
+    // Sets the partial derivatives of the point and the outward normal with respect to (u, v).
+    // Call it after set_face_normal, so the normal derivatives can be flipped to match a normal
+    // that was flipped to face the ray.
+    pub fn set_derivatives(&mut self, dpdu : Vector3, dpdv : Vector3, dndu : Vector3, dndv : Vector3) {
+        let sign = if self.front_face { 1.0 } else { -1.0 };
+
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self.dndu = dndu * sign;
+        self.dndv = dndv * sign;
+    }
+
+    // Works out the surface differentials for a ray with differentials by intersecting the
+    // neighbouring rays with the plane tangent to the hit, then finding the changes in u and v
+    // that best explain the offsets from the hit point (least squares, since dpdu and dpdv span
+    // the plane but generally aren't perpendicular). Without ray differentials, or where the
+    // neighbours miss the plane, there are none.
+    pub fn compute_differentials(&mut self, ray : &Ray) {
+        self.differentials = None;
+        let Some(rays) = ray.differentials else {
+            return;
+        };
+
+        // the true tangent plane, which smooth shading normals can lean away from
+        let tangent_normal = self.dpdu.cross(self.dpdv);
+        let plane_normal = if tangent_normal.near_zero() { self.normal } else { tangent_normal };
+
+        let offset = |origin : Point3, direction : Vector3| {
+            let t = plane_normal.dot(self.point - origin) / plane_normal.dot(direction);
+            if t.is_finite() { Some(origin + direction * t - self.point) } else { None }
+        };
+        let (Some(dpdx), Some(dpdy)) = (offset(rays.rx_origin, rays.rx_direction), offset(rays.ry_origin, rays.ry_direction)) else {
+            return;
+        };
+
+        let ata00 = self.dpdu.dot(self.dpdu);
+        let ata01 = self.dpdu.dot(self.dpdv);
+        let ata11 = self.dpdv.dot(self.dpdv);
+        let inverse_det = 1.0 / (ata00 * ata11 - ata01 * ata01);
+
+        // shapes without (u, v) derivatives leave everything but dpdx and dpdy at zero
+        let solve = |dp : Vector3| {
+            let atb0 = self.dpdu.dot(dp);
+            let atb1 = self.dpdv.dot(dp);
+            let du = (ata11 * atb0 - ata01 * atb1) * inverse_det;
+            let dv = (ata00 * atb1 - ata01 * atb0) * inverse_det;
+
+            if du.is_finite() && dv.is_finite() { (du.clamp(-1e8, 1e8), dv.clamp(-1e8, 1e8)) } else { (0.0, 0.0) }
+        };
+        let (dudx, dvdx) = solve(dpdx);
+        let (dudy, dvdy) = solve(dpdy);
+
+        self.differentials = Some(SurfaceDifferentials { dpdx, dpdy, dudx, dvdx, dudy, dvdy });
+    }
 }
 
-pub trait Hittable {
+// Scene queries along a ray, each only considering intersections with t inside ray_t. Send + Sync
+// so a scene can be shared between render threads.
+pub trait Hittable: Send + Sync {
+    // the closest intersection
     fn hit(&self, ray : &Ray, ray_t : Interval) -> Option<HitRecord>;
-}
\ No newline at end of file
+
+    // box enclosing the whole object, used to build the BVH
+    fn bounding_box(&self) -> Aabb;
+
+    // whether anything at all is hit. Good for shadow and occlusion tests, since it can stop at
+    // the first intersection found instead of searching for the closest one.
+    fn any_hit(&self, ray : &Ray, ray_t : Interval) -> bool {
+        self.hit(ray, ray_t).is_some()
+    }
+
+    // adds every intersection to hits, in no particular order. Shapes that can be hit more than
+    // once along a ray should override this, the default only reports the closest hit.
+    fn collect_hits(&self, ray : &Ray, ray_t : Interval, hits : &mut Vec<HitRecord>) {
+        if let Some(record) = self.hit(ray, ray_t) {
+            hits.push(record);
+        }
+    }
+
+    // every intersection, sorted from nearest to farthest
+    fn all_hits(&self, ray : &Ray, ray_t : Interval) -> Vec<HitRecord> {
+        let mut hits = vec![];
+        self.collect_hits(ray, ray_t, &mut hits);
+        hits.sort_by(|a, b| a.t.total_cmp(&b.t));
+
+        hits
+    }
+
+    // Shapes that can be lights implement these two so the renderer can aim rays straight at them.
+    // random picks a direction from origin towards a point on the shape, and pdf_value is the
+    // density of that choice with respect to solid angle around origin. The default can't be
+    // sampled: every direction has a density of zero.
+    fn pdf_value(&self, _origin : Point3, _direction : Vector3) -> f64 {
+        0.0
+    }
+
+    fn random(&self, _origin : Point3, _sampler : &mut Sampler) -> Vector3 {
+        Vector3::new(1.0, 0.0, 0.0)
+    }
+}
+// Shared by the shapes' tests
+#[cfg(test)]
+pub(crate) mod test_support {
+    use std::f64::consts::PI;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+    use crate::vector3::{random_unit_vector, Point3};
+
+    const SAMPLES: usize = 100_000;
+
+    // Two Monte Carlo estimates of the solid angle the object covers from origin: the share of
+    // uniformly random directions that hit it, and the mean of 1 / pdf_value over the directions
+    // random picks. They only agree when random and pdf_value describe the same distribution.
+    pub fn solid_angles(object: &dyn Hittable, origin: Point3) -> (f64, f64) {
+        let mut sampler = Sampler::new(17);
+
+        let hits = (0..SAMPLES)
+            .filter(|_| object.hit(&Ray::new(origin, random_unit_vector(&mut sampler)), Interval::new(0.001, f64::INFINITY)).is_some())
+            .count();
+        let uniform = 4.0 * PI * hits as f64 / SAMPLES as f64;
+
+        let sampled = (0..SAMPLES)
+            .map(|_| 1.0 / object.pdf_value(origin, object.random(origin, &mut sampler)))
+            .sum::<f64>() / SAMPLES as f64;
+
+        (uniform, sampled)
+    }
+
+    // pdf_value integrated over the whole sphere of directions, which should be 1
+    pub fn pdf_integral(object: &dyn Hittable, origin: Point3) -> f64 {
+        let mut sampler = Sampler::new(19);
+
+        4.0 * PI * (0..SAMPLES).map(|_| object.pdf_value(origin, random_unit_vector(&mut sampler))).sum::<f64>() / SAMPLES as f64
+    }
+}
@@ -1,24 +1,52 @@
 use std::sync::Arc;
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
-use crate::material::Matte;
 use crate::ray::Ray;
-use crate::vector3::{Colour, Vector3};
+use crate::sampler::Sampler;
+use crate::vector3::{Point3, Vector3};
 
+// Objects are reference counted so the same object can be in more than one list, such as an area
+// light that's part of the world and also in the list of lights to sample
 pub struct HittableList {
-    objects: Vec<Box<dyn Hittable>>
+    objects: Vec<Arc<dyn Hittable>>,
+    bbox: Aabb
 }
 
 impl HittableList {
     pub fn new() -> HittableList {
         HittableList {
             objects: vec![],
+            bbox: Aabb::EMPTY
         }
     }
 
     pub fn add(&mut self, object : Box<dyn Hittable>) {
+        self.add_shared(Arc::from(object));
+    }
+
+    pub fn add_shared(&mut self, object : Arc<dyn Hittable>) {
+        self.bbox = Aabb::union(self.bbox, object.bounding_box());
         self.objects.push(object);
     }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn into_objects(self) -> Vec<Arc<dyn Hittable>> {
+        self.objects
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        HittableList::new()
+    }
 }
 
 impl Hittable for HittableList {
@@ -26,13 +54,131 @@ impl Hittable for HittableList {
         let mut record: Option<HitRecord> = None;
         let mut closest_so_far = ray_t.max;
 
+        // only look for hits closer than the best one so far, so the nearest object wins no
+        // matter where it is in the list
         for object in &self.objects {
-            if let Some(object_record) = object.hit(ray, ray_t) {
+            if let Some(object_record) = object.hit(ray, Interval::new(ray_t.min, closest_so_far)) {
                 closest_so_far = object_record.t;
                 record = Some(object_record);
             }
         }
 
-        return record;
+        record
+    }
+
+    fn any_hit(&self, ray: &Ray, ray_t: Interval) -> bool {
+        self.objects.iter().any(|object| object.any_hit(ray, ray_t))
+    }
+
+    fn collect_hits(&self, ray: &Ray, ray_t: Interval, hits: &mut Vec<HitRecord>) {
+        for object in &self.objects {
+            object.collect_hits(ray, ray_t, hits);
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // sampling picks one of the objects at random, so the density is the average of theirs
+    fn pdf_value(&self, origin: Point3, direction: Vector3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let total: f64 = self.objects.iter().map(|object| object.pdf_value(origin, direction)).sum();
+
+        total / self.objects.len() as f64
     }
-}
\ No newline at end of file
+
+    fn random(&self, origin: Point3, sampler: &mut Sampler) -> Vector3 {
+        if self.objects.is_empty() {
+            return Vector3::new(1.0, 0.0, 0.0);
+        }
+
+        let index = ((sampler.random() * self.objects.len() as f64) as usize).min(self.objects.len() - 1);
+
+        self.objects[index].random(origin, sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+    use crate::interval::Interval;
+    use crate::material::Matte;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::vector3::{Colour, Point3, Vector3};
+
+    // two spheres of radius 1 overlapping along the -z axis: the near one spans t = 2..4 and the
+    // far one spans t = 3..5 for a ray from the origin
+    fn overlapping_spheres(near_first: bool) -> HittableList {
+        let material = Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)));
+        let near = Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, material.clone());
+        let far = Sphere::new(Point3::new(0.0, 0.0, -4.0), 1.0, material);
+
+        let mut world = HittableList::new();
+        if near_first {
+            world.add(Box::new(near));
+            world.add(Box::new(far));
+        } else {
+            world.add(Box::new(far));
+            world.add(Box::new(near));
+        }
+
+        world
+    }
+
+    fn forward_ray() -> Ray {
+        Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn hit_returns_nearest_object_in_either_order() {
+        for near_first in [true, false] {
+            let world = overlapping_spheres(near_first);
+            let record = world.hit(&forward_ray(), Interval::new(0.001, f64::INFINITY)).unwrap();
+
+            assert!((record.t - 2.0).abs() < 1e-9, "expected t = 2, got {}", record.t);
+        }
+    }
+
+    #[test]
+    fn hit_respects_interval() {
+        let world = overlapping_spheres(false);
+        let record = world.hit(&forward_ray(), Interval::new(4.5, f64::INFINITY)).unwrap();
+
+        assert!((record.t - 5.0).abs() < 1e-9);
+        assert!(world.hit(&forward_ray(), Interval::new(5.5, f64::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn any_hit_detects_occlusion() {
+        let world = overlapping_spheres(true);
+
+        assert!(world.any_hit(&forward_ray(), Interval::new(0.001, 2.5)));
+        assert!(!world.any_hit(&forward_ray(), Interval::new(0.001, 1.5)));
+
+        let away = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        assert!(!world.any_hit(&away, Interval::new(0.001, f64::INFINITY)));
+    }
+
+    #[test]
+    fn all_hits_returns_every_intersection_sorted() {
+        let world = overlapping_spheres(false);
+        let hits = world.all_hits(&forward_ray(), Interval::new(0.001, f64::INFINITY));
+        let ts: Vec<f64> = hits.iter().map(|record| record.t).collect();
+
+        assert_eq!(ts.len(), 4);
+        for (t, expected) in ts.iter().zip([2.0, 3.0, 4.0, 5.0]) {
+            assert!((t - expected).abs() < 1e-9, "expected {:?}", ts);
+        }
+
+        // entering hits face the ray, exiting hits are seen from inside
+        assert!(hits[0].front_face && hits[1].front_face);
+        assert!(!hits[2].front_face && !hits[3].front_face);
+    }
+}
@@ -1,10 +1,13 @@
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Interval {
     pub min : f64,
     pub max : f64
 }
 
 impl Interval {
+    pub const EMPTY: Interval = Interval { min: f64::INFINITY, max: f64::NEG_INFINITY };
+    pub const UNIVERSE: Interval = Interval { min: f64::NEG_INFINITY, max: f64::INFINITY };
+
     pub fn new(min : f64, max: f64) -> Interval {
         Interval {
             min,
@@ -12,18 +15,34 @@ impl Interval {
         }
     }
 
+    // smallest interval containing both a and b
+    pub fn union(a: Interval, b: Interval) -> Interval {
+        Interval::new(a.min.min(b.min), a.max.max(b.max))
+    }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    // grows the interval by delta, split evenly between both ends
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+
+        Interval::new(self.min - padding, self.max + padding)
+    }
+
     pub fn contains(&self, x: f64) -> bool {
-        return self.min <= x && self.max >= x;
+        self.min <= x && self.max >= x
     }
 
     pub fn surrounds(&self, x : f64) -> bool {
-        return self.min < x && x < self.max;
+        self.min < x && x < self.max
     }
 
     pub fn clamp(&self, x: f64) -> f64 {
         if x < self.min { return self.min }
         if x > self.max { return self.max }
 
-        return x;
+        x
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,53 @@
+// Ray tracing in one weekend, grown into a small path tracing library. The binary in main.rs is
+// one consumer of it; anything here can be used to build and render scenes from other tools.
+
+// maths
+pub mod vector3;
+pub mod ray;
+pub mod interval;
+pub mod aabb;
+pub mod onb;
+pub mod sampler;
+pub mod perlin;
+
+// geometry
+pub mod hittable;
+pub mod hittable_list;
+pub mod sphere;
+pub mod triangle;
+pub mod quad;
+pub mod disk;
+pub mod plane;
+pub mod cuboid;
+pub mod mesh;
+pub mod bvh;
+
+// shading
+pub mod material;
+pub mod light;
+pub mod texture;
+pub mod mipmap;
+
+// rendering and output
+pub mod camera;
+pub mod framebuffer;
+pub mod output;
+
+// getting scenes in
+pub mod loaders;
+pub mod scene;
+
+mod util;
+
+pub use crate::bvh::BvhNode;
+pub use crate::camera::{Camera, CameraBuilder, CameraError, CameraSettings};
+pub use crate::framebuffer::Framebuffer;
+pub use crate::hittable::{HitRecord, Hittable, SurfaceDifferentials};
+pub use crate::hittable_list::HittableList;
+pub use crate::light::{DirectionalLight, Light, Lights, PointLight, SpotLight};
+pub use crate::material::{Dielectric, DiffuseLight, Material, Matte, Metal};
+pub use crate::mipmap::{MipMap, TextureFilter};
+pub use crate::ray::{Ray, RayDifferentials};
+pub use crate::scene::Scene;
+pub use crate::texture::{Checker, ColourRamp, ImageTexture, NoisePattern, NoiseTexture, SolidColour, Texture};
+pub use crate::vector3::{Colour, Point3, Vector3};
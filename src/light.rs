@@ -0,0 +1,219 @@
+use std::sync::Arc;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::vector3::{Colour, Point3, Vector3};
+
+// Light arriving at a point from an analytic light: the unit direction towards the light, how far
+// away it is (infinite for directional lights) and the incident radiance, already scaled by any
+// falloff with distance or angle
+pub struct LightSample {
+    pub direction: Vector3,
+    pub distance: f64,
+    pub radiance: Colour
+}
+
+// Idealised lights with no surface: each lights a point from exactly one direction, so the only
+// way to find them is to ask, and camera or scattered rays can never hit one
+pub trait Light: Send + Sync {
+    // None if the light doesn't reach the point at all, like outside a spotlight's cone
+    fn sample(&self, point: Point3) -> Option<LightSample>;
+}
+
+// Shines equally in every direction from a position, falling off with the square of the distance
+pub struct PointLight {
+    position: Point3,
+    intensity: Colour
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Colour) -> PointLight {
+        PointLight { position, intensity }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: Point3) -> Option<LightSample> {
+        let offset = self.position - point;
+        let distance = offset.length();
+        if distance <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: offset / distance,
+            distance,
+            radiance: self.intensity / (distance * distance)
+        })
+    }
+}
+
+// A point light limited to a cone around direction. Full intensity inside inner_angle, fading
+// smoothly to nothing at outer_angle (both measured from the axis, in degrees).
+pub struct SpotLight {
+    position: Point3,
+    direction: Vector3,
+    intensity: Colour,
+    cos_inner: f64,
+    cos_outer: f64
+}
+
+impl SpotLight {
+    pub fn new(position: Point3, direction: Vector3, intensity: Colour, inner_angle: f64, outer_angle: f64) -> SpotLight {
+        let outer_angle = outer_angle.max(inner_angle);
+
+        SpotLight {
+            position,
+            direction: direction.unit(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos()
+        }
+    }
+
+    fn falloff(&self, cos_angle: f64) -> f64 {
+        if cos_angle >= self.cos_inner {
+            return 1.0;
+        }
+
+        if cos_angle <= self.cos_outer {
+            return 0.0;
+        }
+
+        // smoothstep between the edges of the two cones
+        let x = (cos_angle - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        x * x * (3.0 - 2.0 * x)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: Point3) -> Option<LightSample> {
+        let offset = self.position - point;
+        let distance = offset.length();
+        if distance <= 0.0 {
+            return None;
+        }
+
+        let direction = offset / distance;
+        let falloff = self.falloff(-direction.dot(self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.intensity * (falloff / (distance * distance))
+        })
+    }
+}
+
+// Parallel light from infinitely far away, like the sun. direction is the way the light travels.
+pub struct DirectionalLight {
+    direction: Vector3,
+    irradiance: Colour
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vector3, irradiance: Colour) -> DirectionalLight {
+        DirectionalLight { direction: direction.unit(), irradiance }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Point3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance
+        })
+    }
+}
+
+// Every light in a scene. The area lights are emissive objects that are also part of the world,
+// sampled by picking points on them; the analytic ones exist only here.
+#[derive(Default)]
+pub struct Lights {
+    pub area: HittableList,
+    pub analytic: Vec<Box<dyn Light>>
+}
+
+impl Lights {
+    pub fn new() -> Lights {
+        Lights::default()
+    }
+
+    pub fn add_area(&mut self, object: Arc<dyn Hittable>) {
+        self.area.add_shared(object);
+    }
+
+    pub fn add(&mut self, light: Box<dyn Light>) {
+        self.analytic.push(light);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.area.is_empty() && self.analytic.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+    use crate::vector3::{Colour, Point3, Vector3};
+
+    fn white() -> Colour {
+        Colour::new(1.0, 1.0, 1.0)
+    }
+
+    #[test]
+    fn point_lights_fall_off_with_the_square_of_the_distance() {
+        let light = PointLight::new(Point3::new(0.0, 4.0, 0.0), Colour::new(8.0, 4.0, 2.0));
+
+        let near = light.sample(Point3::new(0.0, 2.0, 0.0)).unwrap();
+        let far = light.sample(Point3::new(0.0, -4.0, 0.0)).unwrap();
+
+        assert_eq!((near.distance, far.distance), (2.0, 8.0));
+        assert_eq!((near.direction.y, far.direction.y), (1.0, 1.0));
+        assert_eq!((near.radiance.x, near.radiance.y, near.radiance.z), (2.0, 1.0, 0.5));
+        assert_eq!(near.radiance.x / far.radiance.x, 16.0);
+
+        assert!(light.sample(Point3::new(0.0, 4.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn spotlights_fade_between_their_cones() {
+        // pointing straight down from a height of 1, so a point x along the floor is atan(x) off
+        // the axis. Scaling by the squared distance leaves just the falloff.
+        let light = SpotLight::new(Point3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0), white(), 20.0, 40.0);
+        let falloff = |degrees: f64| {
+            light.sample(Point3::new(degrees.to_radians().tan(), 0.0, 0.0))
+                .map_or(0.0, |sample| sample.radiance.x * sample.distance * sample.distance)
+        };
+
+        for degrees in [0.0, 10.0, 19.9] {
+            assert!((falloff(degrees) - 1.0).abs() < 1e-9, "{} degrees", degrees);
+        }
+        for degrees in [40.1, 60.0, 89.0] {
+            assert_eq!(falloff(degrees), 0.0, "{} degrees", degrees);
+        }
+
+        let mut previous = 1.0;
+        for step in 1..40 {
+            let value = falloff(20.0 + step as f64 * 0.5);
+            assert!(value < previous && value > 0.0, "{} after {}", value, previous);
+            previous = value;
+        }
+    }
+
+    #[test]
+    fn directional_lights_ignore_distance() {
+        let light = DirectionalLight::new(Vector3::new(0.0, -2.0, 0.0), Colour::new(0.5, 0.5, 0.5));
+
+        for point in [Point3::new(0.0, 0.0, 0.0), Point3::new(100.0, -1e4, 7.0)] {
+            let sample = light.sample(point).unwrap();
+
+            assert_eq!((sample.direction.x, sample.direction.y, sample.direction.z), (0.0, 1.0, 0.0));
+            assert_eq!(sample.distance, f64::INFINITY);
+            assert_eq!(sample.radiance.x, 0.5);
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use gltf::camera::Projection;
+use gltf::mesh::Mode;
+use gltf::Node;
+use crate::camera::CameraSettings;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::loaders::LoadError;
+use crate::material::{Dielectric, DiffuseLight, Material, Matte, Metal};
+use crate::mesh::{MeshData, TriangleMesh};
+use crate::vector3::{Colour, Point3, Vector3};
+
+// Everything taken from a glTF file: its meshes in world space (with the ones using emissive
+// materials also in lights), and the settings of each of its perspective cameras (in node order)
+// with the rest left at their defaults
+pub struct GltfScene {
+    pub world: HittableList,
+    pub lights: HittableList,
+    pub cameras: Vec<CameraSettings>
+}
+
+// Loads a .gltf (with external or embedded buffers) or .glb file. The node hierarchy of the
+// default scene (or the first one) is flattened by baking every node's world transform into its
+// mesh, and metallic-roughness materials are approximated with Matte, Metal and Dielectric.
+pub fn load_gltf(path: &Path) -> Result<GltfScene, LoadError> {
+    let bytes = fs::read(path).map_err(|error| LoadError::io(path, error))?;
+
+    parse_gltf(&bytes, path)
+}
+
+// Parses the contents of a .gltf or .glb file. path is used for error messages and to find
+// external buffers.
+pub fn parse_gltf(bytes: &[u8], path: &Path) -> Result<GltfScene, LoadError> {
+    let gltf = gltf::Gltf::from_slice(bytes).map_err(|error| gltf_error(path, error))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let buffers = gltf::import_buffers(&gltf.document, Some(base), gltf.blob.clone())
+        .map_err(|error| gltf_error(path, error))?;
+
+    let mut loader = Loader {
+        path,
+        buffers: &buffers,
+        materials: HashMap::new(),
+        scene: GltfScene { world: HittableList::new(), lights: HittableList::new(), cameras: vec![] }
+    };
+
+    let scene = gltf.document.default_scene().or_else(|| gltf.document.scenes().next());
+    if let Some(scene) = scene {
+        for node in scene.nodes() {
+            loader.load_node(&node, &IDENTITY)?;
+        }
+    }
+
+    Ok(loader.scene)
+}
+
+fn gltf_error(path: &Path, error: gltf::Error) -> LoadError {
+    LoadError::format(path, error.to_string())
+}
+
+struct Loader<'a> {
+    path: &'a Path,
+    buffers: &'a [gltf::buffer::Data],
+    materials: HashMap<Option<usize>, Arc<dyn Material>>,
+    scene: GltfScene
+}
+
+impl Loader<'_> {
+    fn load_node(&mut self, node: &Node, parent: &Matrix) -> Result<(), LoadError> {
+        let transform = multiply(parent, &node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            if let Some(mesh) = self.load_mesh(&mesh, &transform)? {
+                let emits_light = mesh.emits_light();
+                let mesh: Arc<dyn Hittable> = Arc::new(mesh);
+                self.scene.world.add_shared(Arc::clone(&mesh));
+                if emits_light {
+                    self.scene.lights.add_shared(mesh);
+                }
+            }
+        }
+
+        if let Some(camera) = node.camera() {
+            if let Projection::Perspective(perspective) = camera.projection() {
+                self.scene.cameras.push(camera_settings(&perspective, &transform));
+            }
+        }
+
+        for child in node.children() {
+            self.load_node(&child, &transform)?;
+        }
+
+        Ok(())
+    }
+
+    // All triangle primitives of a mesh become one TriangleMesh, with a material per primitive
+    fn load_mesh(&mut self, mesh: &gltf::Mesh, transform: &Matrix) -> Result<Option<TriangleMesh>, LoadError> {
+        let normal_transform = normal_matrix(transform);
+        let mut data = MeshData::default();
+        let mut materials = vec![];
+        let mut has_normals = true;
+        let mut has_colours = false;
+        let mut has_uvs = false;
+
+        let primitives: Vec<gltf::Primitive> = mesh.primitives().filter(|primitive| primitive.mode() == Mode::Triangles).collect();
+
+        // first pass to see which attributes every primitive has, since the buffers have to cover
+        // every vertex or none of them
+        for primitive in &primitives {
+            let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+            has_normals &= reader.read_normals().is_some();
+            has_colours |= reader.read_colors(0).is_some();
+            has_uvs |= reader.read_tex_coords(0).is_some();
+        }
+
+        for primitive in &primitives {
+            let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+            let Some(positions) = reader.read_positions() else {
+                return Err(LoadError::format(self.path, format!("mesh {} has a primitive without positions", mesh.index())));
+            };
+
+            let first_vertex = data.positions.len();
+            data.positions.extend(positions.map(|position| transform_point(transform, position)));
+            let vertex_count = data.positions.len() - first_vertex;
+
+            if has_normals {
+                if let Some(normals) = reader.read_normals() {
+                    data.normals.extend(normals.map(|normal| transform_vector(&normal_transform, normal).unit()));
+                }
+            }
+
+            if has_colours {
+                match reader.read_colors(0) {
+                    Some(colours) => data.colours.extend(colours.into_rgb_f32().map(|[r, g, b]| Colour::new(r as f64, g as f64, b as f64))),
+                    None => data.colours.extend((0..vertex_count).map(|_| Colour::new(1.0, 1.0, 1.0)))
+                }
+            }
+
+            if has_uvs {
+                match reader.read_tex_coords(0) {
+                    Some(uvs) => data.uvs.extend(uvs.into_f32().map(|[u, v]| (u as f64, v as f64))),
+                    None => data.uvs.extend((0..vertex_count).map(|_| (0.0, 0.0)))
+                }
+            }
+
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|index| first_vertex + index as usize).collect(),
+                None => (first_vertex..first_vertex + vertex_count).collect()
+            };
+
+            let material_id = materials.len();
+            materials.push(self.material(&primitive.material()));
+
+            for triangle in indices.chunks_exact(3) {
+                data.triangles.push([triangle[0], triangle[1], triangle[2]]);
+                data.material_ids.push(material_id);
+            }
+        }
+
+        if data.triangles.is_empty() {
+            return Ok(None);
+        }
+
+        TriangleMesh::new(data, materials)
+            .map(Some)
+            .map_err(|error| LoadError::mesh(self.path, error))
+    }
+
+    // Emissive materials become lights, transmissive or see-through ones become glass, mostly
+    // metallic ones become Metal with the roughness as fuzz, and everything else is Matte in the
+    // base colour. Materials are shared between every primitive that uses them.
+    fn material(&mut self, material: &gltf::Material) -> Arc<dyn Material> {
+        if let Some(existing) = self.materials.get(&material.index()) {
+            return Arc::clone(existing);
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, alpha] = pbr.base_color_factor();
+        let base_colour = Colour::new(r as f64, g as f64, b as f64);
+        let transmission = material.transmission().map_or(0.0, |transmission| transmission.transmission_factor());
+
+        let [er, eg, eb] = material.emissive_factor();
+        let emission = Colour::new(er as f64, eg as f64, eb as f64) * material.emissive_strength().unwrap_or(1.0) as f64;
+
+        let converted: Arc<dyn Material> = if !emission.near_zero() {
+            Arc::new(DiffuseLight::new(emission))
+        } else if transmission > 0.5 || (material.alpha_mode() == gltf::material::AlphaMode::Blend && alpha < 1.0) {
+            Arc::new(Dielectric::new(material.ior().unwrap_or(1.5) as f64))
+        } else if pbr.metallic_factor() >= 0.5 {
+            Arc::new(Metal::new(base_colour, pbr.roughness_factor() as f64))
+        } else {
+            Arc::new(Matte::new(base_colour))
+        };
+
+        self.materials.insert(material.index(), Arc::clone(&converted));
+
+        converted
+    }
+}
+
+// glTF cameras look down their local -z axis with +y up
+fn camera_settings(perspective: &gltf::camera::Perspective, transform: &Matrix) -> CameraSettings {
+    let look_from = transform_point(transform, [0.0, 0.0, 0.0]);
+    let forward = transform_vector(transform, [0.0, 0.0, -1.0]).unit();
+    let vup = transform_vector(transform, [0.0, 1.0, 0.0]).unit();
+
+    let mut settings = CameraSettings {
+        vfov: (perspective.yfov() as f64).to_degrees(),
+        look_from,
+        look_at: look_from + forward,
+        vup,
+        defocus_angle: 0.0,
+        focus_dist: 1.0,
+        ..CameraSettings::default()
+    };
+
+    if let Some(aspect_ratio) = perspective.aspect_ratio() {
+        settings.aspect_ratio = aspect_ratio as f64;
+    }
+
+    settings
+}
+
+// column-major 4x4 matrix, as glTF stores them
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0]
+];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (column, result_column) in result.iter_mut().enumerate() {
+        for (row, value) in result_column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+
+    result
+}
+
+fn transform_point(m: &Matrix, [x, y, z]: [f32; 3]) -> Point3 {
+    let row = |r: usize| (m[0][r] * x + m[1][r] * y + m[2][r] * z + m[3][r]) as f64;
+
+    Point3::new(row(0), row(1), row(2))
+}
+
+fn transform_vector(m: &Matrix, [x, y, z]: [f32; 3]) -> Vector3 {
+    let row = |r: usize| (m[0][r] * x + m[1][r] * y + m[2][r] * z) as f64;
+
+    Vector3::new(row(0), row(1), row(2))
+}
+
+// Normals have to be transformed by the inverse transpose of the upper 3x3, otherwise non-uniform
+// scaling would tilt them off the surface. Returned padded to a 4x4 for transform_vector.
+fn normal_matrix(m: &Matrix) -> Matrix {
+    let a = |column: usize, row: usize| m[column][row];
+
+    // cofactors of the 3x3 part are the inverse transpose times the determinant. Normals get
+    // normalised anyway, so only the determinant's sign matters: it's negative for mirroring
+    // transforms and would turn the normals inside out, so it's divided back out.
+    let cofactor = |column: usize, row: usize| {
+        let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        a(c0, r0) * a(c1, r1) - a(c1, r0) * a(c0, r1)
+    };
+
+    let determinant: f32 = (0..3).map(|column| a(column, 0) * cofactor(column, 0)).sum();
+    let sign = if determinant < 0.0 { -1.0 } else { 1.0 };
+
+    let mut result = IDENTITY;
+    for (column, result_column) in result.iter_mut().enumerate().take(3) {
+        for (row, value) in result_column.iter_mut().enumerate().take(3) {
+            *value = sign * cofactor(column, row);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::hittable::Hittable;
+    use crate::loaders::LoadError;
+    use crate::loaders::test_support::expect_format_error;
+    use super::{normal_matrix, parse_gltf, transform_vector, GltfScene, IDENTITY};
+
+    // A .glb holding json and a binary buffer of three float positions followed by three u16
+    // indices. The json gets the buffer's length in place of {length}.
+    fn glb(json: &str, indices: [u16; 3]) -> Vec<u8> {
+        let mut buffer: Vec<u8> = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+            .iter().flatten().flat_map(|value| value.to_le_bytes()).collect();
+        buffer.extend(indices.iter().flat_map(|index| index.to_le_bytes()));
+        buffer.resize(buffer.len().next_multiple_of(4), 0);
+
+        let mut json = json.replace("{length}", &buffer.len().to_string()).into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+
+        let mut bytes = b"glTF".to_vec();
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(((12 + 8 + json.len() + 8 + buffer.len()) as u32).to_le_bytes());
+        bytes.extend((json.len() as u32).to_le_bytes());
+        bytes.extend(b"JSON");
+        bytes.extend(json);
+        bytes.extend((buffer.len() as u32).to_le_bytes());
+        bytes.extend(b"BIN\0");
+        bytes.extend(buffer);
+
+        bytes
+    }
+
+    // one triangle in a node moved up by 5 with a child camera, using the given material
+    fn triangle_json(material: &str) -> String {
+        format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [
+                {{ "mesh": 0, "translation": [0, 5, 0], "children": [1] }},
+                {{ "camera": 0, "translation": [0, 0, 3] }}
+            ],
+            "cameras": [{{ "type": "perspective", "perspective": {{ "yfov": 0.5, "aspectRatio": 2.0, "znear": 0.1 }} }}],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }}] }}],
+            "materials": [{}],
+            "buffers": [{{ "byteLength": {{length}} }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+                {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ]
+        }}"#, material)
+    }
+
+    fn parse(bytes: &[u8]) -> Result<GltfScene, LoadError> {
+        parse_gltf(bytes, Path::new("test.glb"))
+    }
+
+    #[test]
+    fn bakes_node_transforms_into_meshes_and_cameras() {
+        let scene = parse(&glb(&triangle_json("{}"), [0, 1, 2])).unwrap();
+
+        assert_eq!(scene.world.len(), 1);
+        assert!(scene.lights.is_empty());
+
+        // boxes get padded a little
+        let bbox = scene.world.bounding_box();
+        assert!((bbox.y.min - 5.0).abs() < 1e-3 && (bbox.y.max - 6.0).abs() < 1e-3, "{:?}", (bbox.y.min, bbox.y.max));
+
+        // the camera's translation is relative to the mesh's node
+        let camera = scene.cameras[0];
+        assert_eq!((camera.look_from.x, camera.look_from.y, camera.look_from.z), (0.0, 5.0, 3.0));
+        assert!((camera.vfov - 0.5f64.to_degrees()).abs() < 1e-4);
+        assert_eq!(camera.aspect_ratio, 2.0);
+    }
+
+    #[test]
+    fn emissive_meshes_are_lights() {
+        let scene = parse(&glb(&triangle_json(r#"{ "emissiveFactor": [1, 0.5, 0.5] }"#), [0, 1, 2])).unwrap();
+
+        assert_eq!(scene.world.len(), 1);
+        assert_eq!(scene.lights.len(), 1);
+    }
+
+    #[test]
+    fn reports_out_of_range_indices() {
+        let result = parse(&glb(&triangle_json("{}"), [0, 1, 7]));
+
+        assert!(matches!(result, Err(LoadError::Mesh { .. })));
+    }
+
+    #[test]
+    fn reports_broken_files() {
+        expect_format_error(parse(b"{ not json"));
+
+        // a buffer in a file that doesn't exist
+        let json = triangle_json("{}").replace(r#"{ "byteLength": {length} }"#, r#"{ "byteLength": 44, "uri": "missing.bin" }"#);
+        expect_format_error(parse(json.as_bytes()));
+    }
+
+    #[test]
+    fn normals_survive_scaling_and_mirroring() {
+        let normal = |scale: [f32; 3], normal: [f32; 3]| {
+            let mut transform = IDENTITY;
+            for (axis, factor) in scale.iter().enumerate() {
+                transform[axis][axis] = *factor;
+            }
+            let normal = transform_vector(&normal_matrix(&transform), normal).unit();
+            (normal.x, normal.y, normal.z)
+        };
+
+        // squashing a 45 degree slope in x makes it steeper, so its normal leans further over
+        let (x, y, z) = normal([0.5, 1.0, 1.0], [1.0, 1.0, 0.0]);
+        assert!((x - 2.0 / 5f64.sqrt()).abs() < 1e-6 && (y - 1.0 / 5f64.sqrt()).abs() < 1e-6 && z == 0.0, "{:?}", (x, y, z));
+
+        // mirroring in x flips normals along x with the surface, and leaves the others alone
+        assert_eq!(normal([-1.0, 1.0, 1.0], [1.0, 0.0, 0.0]), (-1.0, 0.0, 0.0));
+        assert_eq!(normal([-1.0, 1.0, 1.0], [0.0, 0.0, 1.0]), (0.0, 0.0, 1.0));
+        assert_eq!(normal([-2.0, -3.0, -0.5], [0.0, 1.0, 0.0]), (0.0, -1.0, 0.0));
+    }
+}
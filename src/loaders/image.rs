@@ -0,0 +1,217 @@
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use crate::loaders::LoadError;
+use crate::vector3::Colour;
+
+// Largest image the loaders accept, in pixels. Headers can claim any size, and anything bigger
+// than this is far more likely to be a corrupt file than a texture.
+const MAX_PIXELS: usize = 1 << 28;
+
+// A decoded picture in linear colour, stored row by row from the top left
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Colour>
+}
+
+impl Image {
+    pub fn pixel(&self, x: usize, y: usize) -> Colour {
+        self.pixels[y * self.width + x]
+    }
+}
+
+// Loads a PNG or PPM (P3 or P6) file. Both store sRGB-encoded colours, which are converted to
+// linear so they can be used as albedos. Alpha channels are ignored.
+pub fn load_image(path: &Path) -> Result<Image, LoadError> {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+
+    match extension.to_ascii_lowercase().as_str() {
+        "png" => load_png(path),
+        "ppm" => load_ppm(path),
+        _ => Err(LoadError::format(path, format!("unsupported image format '{}'", extension)))
+    }
+}
+
+fn load_png(path: &Path) -> Result<Image, LoadError> {
+    let file = File::open(path).map_err(|error| LoadError::io(path, error))?;
+    let decoding_error = |error: png::DecodingError| LoadError::format(path, error.to_string());
+
+    // expands palettes and packed greyscale to whole bytes, leaving 8 or 16 bits per channel
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(decoding_error)?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(decoding_error)?;
+
+    let channels = info.color_type.samples();
+    let (maximum, bytes_per_sample) = match info.bit_depth {
+        png::BitDepth::Sixteen => (65535.0, 2),
+        _ => (255.0, 1)
+    };
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+    pixel_count(path, width, height)?;
+    let sample = |row: &[u8], index: usize| match bytes_per_sample {
+        2 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]) as f64 / maximum,
+        _ => row[index] as f64 / maximum
+    };
+
+    let mut pixels = Vec::new();
+    for row in buffer.chunks(info.line_size).take(height) {
+        for x in 0..width {
+            let first = x * channels;
+            // greyscale, with or without alpha, has one colour sample
+            let colour = if channels < 3 {
+                let grey = sample(row, first);
+                Colour::new(grey, grey, grey)
+            } else {
+                Colour::new(sample(row, first), sample(row, first + 1), sample(row, first + 2))
+            };
+            pixels.push(srgb_to_linear(colour));
+        }
+    }
+
+    Ok(Image { width, height, pixels })
+}
+
+// Netpbm header fields are separated by any whitespace and can have # comments between them. P3
+// keeps every sample as text, P6 has raw bytes (two per sample if maxval is over 255) after the
+// single whitespace character that ends the header.
+fn load_ppm(path: &Path) -> Result<Image, LoadError> {
+    let bytes = fs::read(path).map_err(|error| LoadError::io(path, error))?;
+
+    parse_ppm(&bytes, path)
+}
+
+// Decodes the contents of a PPM file, where path is only used to report errors
+fn parse_ppm(bytes: &[u8], path: &Path) -> Result<Image, LoadError> {
+    let mut position = 0;
+
+    let next_token = |position: &mut usize| -> Option<String> {
+        loop {
+            while *position < bytes.len() && bytes[*position].is_ascii_whitespace() {
+                *position += 1;
+            }
+            if *position < bytes.len() && bytes[*position] == b'#' {
+                while *position < bytes.len() && bytes[*position] != b'\n' {
+                    *position += 1;
+                }
+            } else {
+                break;
+            }
+        }
+
+        let start = *position;
+        while *position < bytes.len() && !bytes[*position].is_ascii_whitespace() {
+            *position += 1;
+        }
+
+        if start == *position { None } else { Some(String::from_utf8_lossy(&bytes[start..*position]).into_owned()) }
+    };
+
+    let magic = next_token(&mut position).unwrap_or_default();
+    if magic != "P3" && magic != "P6" {
+        return Err(LoadError::format(path, "not a P3 or P6 PPM file"));
+    }
+
+    let mut header = [0usize; 3];
+    for value in header.iter_mut() {
+        *value = next_token(&mut position)
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| LoadError::format(path, "bad PPM header"))?;
+    }
+
+    let [width, height, maxval] = header;
+    if width == 0 || height == 0 || maxval == 0 || maxval > 65535 {
+        return Err(LoadError::format(path, format!("bad PPM size {}x{} or maxval {}", width, height, maxval)));
+    }
+
+    let count = pixel_count(path, width, height)? * 3;
+    let samples: Vec<f64> = if magic == "P3" {
+        // grown as the samples are read, since the header's size can't be trusted until they are
+        let mut samples = Vec::new();
+        for _ in 0..count {
+            let value: usize = next_token(&mut position)
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| LoadError::format(path, "PPM pixel data is short or not numbers"))?;
+            samples.push(value as f64);
+        }
+        samples
+    } else {
+        let data = &bytes[(position + 1).min(bytes.len())..];
+        let bytes_per_sample = if maxval > 255 { 2 } else { 1 };
+        if data.len() / bytes_per_sample < count {
+            return Err(LoadError::format(path, "PPM pixel data is short"));
+        }
+
+        (0..count).map(|index| match bytes_per_sample {
+            2 => u16::from_be_bytes([data[2 * index], data[2 * index + 1]]) as f64,
+            _ => data[index] as f64
+        }).collect()
+    };
+
+    let maximum = maxval as f64;
+    let pixels = samples.chunks(3)
+        .map(|rgb| srgb_to_linear(Colour::new(rgb[0] / maximum, rgb[1] / maximum, rgb[2] / maximum)))
+        .collect();
+
+    Ok(Image { width, height, pixels })
+}
+
+// width times height, if that's a size the loaders accept
+fn pixel_count(path: &Path, width: usize, height: usize) -> Result<usize, LoadError> {
+    match width.checked_mul(height) {
+        Some(count) if count <= MAX_PIXELS => Ok(count),
+        _ => Err(LoadError::format(path, format!("image size {}x{} is too large", width, height)))
+    }
+}
+
+// the inverse of the sRGB transfer curve, for components between 0 and 1
+fn srgb_to_linear(colour: Colour) -> Colour {
+    let decode = |component: f64| {
+        let component = component.clamp(0.0, 1.0);
+        if component <= 0.04045 {
+            component / 12.92
+        } else {
+            ((component + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    Colour::new(decode(colour.x), decode(colour.y), decode(colour.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::loaders::test_support::expect_format_error;
+    use super::parse_ppm;
+
+    fn format_error(bytes: &[u8]) -> String {
+        expect_format_error(parse_ppm(bytes, Path::new("test.ppm")))
+    }
+
+    #[test]
+    fn reads_ascii_ppm_with_comments() {
+        let image = parse_ppm(b"P3\n# a comment\n2 1\n255\n255 255 255  0 0 0\n", Path::new("test.ppm")).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixel(0, 0).x, 1.0);
+        assert_eq!(image.pixel(1, 0).x, 0.0);
+    }
+
+    #[test]
+    fn rejects_huge_or_overflowing_sizes() {
+        assert!(format_error(b"P6 100000 100000 255\n").contains("too large"));
+        assert!(format_error(b"P3 18446744073709551615 18446744073709551615 255\n").contains("too large"));
+    }
+
+    #[test]
+    fn rejects_short_pixel_data() {
+        assert!(format_error(b"P6 4 4 255\n\x00\x01\x02").contains("short"));
+        assert!(format_error(b"P3 2 2 255\n1 2 3").contains("short"));
+    }
+}
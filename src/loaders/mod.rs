@@ -0,0 +1,107 @@
+pub mod gltf;
+pub mod image;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::material::Material;
+use crate::mesh::{MeshError, TriangleMesh};
+
+// Loads a mesh file of any supported format, going by its extension. The material is used for
+// every triangle, except in OBJ files where it's only the default for faces without a `usemtl`.
+pub fn load_mesh(path: &Path, material: Arc<dyn Material>) -> Result<TriangleMesh, LoadError> {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+
+    match extension.to_ascii_lowercase().as_str() {
+        "obj" => obj::load_obj(path, material),
+        "ply" => ply::load_ply(path, material),
+        "stl" => stl::load_stl(path, material),
+        _ => Err(LoadError::format(path, format!("unsupported mesh format '{}'", extension)))
+    }
+}
+
+// Why a model file couldn't be loaded. Parse errors point at the offending line, format errors
+// are for problems found where there are no lines to point at (such as binary data).
+#[derive(Debug)]
+pub enum LoadError {
+    Io { path: PathBuf, error: io::Error },
+    Parse { path: PathBuf, line: usize, message: String },
+    Format { path: PathBuf, message: String },
+    Mesh { path: PathBuf, error: MeshError }
+}
+
+impl LoadError {
+    pub fn io(path: &Path, error: io::Error) -> LoadError {
+        LoadError::Io { path: path.to_path_buf(), error }
+    }
+
+    pub fn parse(path: &Path, line: usize, message: impl Into<String>) -> LoadError {
+        LoadError::Parse { path: path.to_path_buf(), line, message: message.into() }
+    }
+
+    pub fn format(path: &Path, message: impl Into<String>) -> LoadError {
+        LoadError::Format { path: path.to_path_buf(), message: message.into() }
+    }
+
+    pub fn mesh(path: &Path, error: MeshError) -> LoadError {
+        LoadError::Mesh { path: path.to_path_buf(), error }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            LoadError::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            LoadError::Format { path, message } => write!(f, "{}: {}", path.display(), message),
+            LoadError::Mesh { path, error } => write!(f, "{}: {}", path.display(), error)
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io { error, .. } => Some(error),
+            LoadError::Mesh { error, .. } => Some(error),
+            LoadError::Parse { .. } | LoadError::Format { .. } => None
+        }
+    }
+}
+
+// Shared by the loaders' tests
+#[cfg(test)]
+pub(crate) mod test_support {
+    use std::sync::Arc;
+    use crate::loaders::LoadError;
+    use crate::material::{Material, Matte};
+    use crate::vector3::Colour;
+
+    // what meshes get when the file doesn't say
+    pub fn default_material() -> Arc<dyn Material> {
+        Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)))
+    }
+
+    // the message of a format error, failing the test on anything else
+    pub fn expect_format_error<T>(result: Result<T, LoadError>) -> String {
+        match result {
+            Err(LoadError::Format { message, .. }) => message,
+            Err(error) => panic!("expected a format error, got {}", error),
+            Ok(_) => panic!("file should have been rejected")
+        }
+    }
+
+    // the line and message of a parse error, failing the test on anything else
+    pub fn expect_parse_error<T>(result: Result<T, LoadError>) -> (usize, String) {
+        match result {
+            Err(LoadError::Parse { line, message, .. }) => (line, message),
+            Err(error) => panic!("expected a parse error, got {}", error),
+            Ok(_) => panic!("file should have been rejected")
+        }
+    }
+}
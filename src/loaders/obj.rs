@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use crate::loaders::LoadError;
+use crate::material::{Dielectric, DiffuseLight, Material, Matte, Metal};
+use crate::mesh::{MeshData, TriangleMesh};
+use crate::vector3::{Colour, Point3, Vector3};
+
+// Loads a Wavefront OBJ file as a single mesh. Polygons are split into triangle fans, and
+// materials from any `mtllib` files are mapped onto Matte, Metal or Dielectric. Faces before the
+// first `usemtl` get default_material.
+pub fn load_obj(path: &Path, default_material: Arc<dyn Material>) -> Result<TriangleMesh, LoadError> {
+    let source = fs::read_to_string(path).map_err(|error| LoadError::io(path, error))?;
+
+    parse_obj(&source, path, default_material)
+}
+
+// Parses OBJ source; path is used for error messages and to find material libraries
+pub fn parse_obj(source: &str, path: &Path, default_material: Arc<dyn Material>) -> Result<TriangleMesh, LoadError> {
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+
+    let mut library: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut materials = vec![default_material];
+    let mut material_indices: HashMap<String, usize> = HashMap::new();
+    let mut current_material = 0;
+
+    // OBJ indexes positions, uvs and normals separately, meshes share one index between them, so
+    // every distinct combination becomes its own vertex
+    let mut vertices: HashMap<(usize, Option<usize>, Option<usize>), usize> = HashMap::new();
+    let mut corners: Vec<(usize, Option<usize>, Option<usize>)> = vec![];
+    let mut data = MeshData::default();
+
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let mut tokens = line.split_whitespace();
+
+        let Some(keyword) = tokens.next() else { continue };
+        let arguments: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vector(&arguments, path, line_number)?),
+            "vn" => normals.push(parse_vector(&arguments, path, line_number)?),
+            "vt" => {
+                let uv = parse_floats(&arguments, 1, path, line_number)?;
+                uvs.push((uv[0], uv.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(LoadError::parse(path, line_number, "a face needs at least 3 vertices"));
+                }
+
+                let mut face = vec![];
+                for argument in &arguments {
+                    let corner = parse_corner(argument, positions.len(), uvs.len(), normals.len(), path, line_number)?;
+                    let next_index = corners.len();
+                    let index = *vertices.entry(corner).or_insert_with(|| {
+                        corners.push(corner);
+                        next_index
+                    });
+                    face.push(index);
+                }
+
+                for i in 1..face.len() - 1 {
+                    data.triangles.push([face[0], face[i], face[i + 1]]);
+                    data.material_ids.push(current_material);
+                }
+            }
+            "mtllib" => {
+                let directory = path.parent().unwrap_or(Path::new(""));
+                for file in &arguments {
+                    library.extend(load_mtl(&directory.join(file))?);
+                }
+            }
+            "usemtl" => {
+                let name = arguments.join(" ");
+                let Some(material) = library.get(&name) else {
+                    return Err(LoadError::parse(path, line_number, format!("unknown material '{}'", name)));
+                };
+
+                current_material = *material_indices.entry(name).or_insert_with(|| {
+                    materials.push(Arc::clone(material));
+                    materials.len() - 1
+                });
+            }
+            // groups, objects, smoothing groups and anything else don't affect the geometry
+            _ => {}
+        }
+    }
+
+    data.positions = corners.iter().map(|&(position, _, _)| positions[position]).collect();
+
+    // only keep normals if every vertex has one, otherwise fall back to flat shading
+    if corners.iter().all(|&(_, _, normal)| normal.is_some()) {
+        data.normals = corners.iter().map(|&(_, _, normal)| normals[normal.unwrap()]).collect();
+    }
+
+    if corners.iter().any(|&(_, uv, _)| uv.is_some()) {
+        data.uvs = corners.iter().map(|&(_, uv, _)| uv.map_or((0.0, 0.0), |uv| uvs[uv])).collect();
+    }
+
+    TriangleMesh::new(data, materials).map_err(|error| LoadError::mesh(path, error))
+}
+
+// Reads the materials of an MTL file by name. Transparent materials become Dielectric with the
+// file's index of refraction, materials that are more specular than diffuse become Metal (the
+// higher the specular exponent, the less fuzz), and everything else is Matte. Anything with an
+// emissive colour (Ke) is a DiffuseLight, whatever else it says.
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, Arc<dyn Material>>, LoadError> {
+    let source = fs::read_to_string(path).map_err(|error| LoadError::io(path, error))?;
+
+    parse_mtl(&source, path)
+}
+
+// Parses MTL source, where path is only used for error messages
+pub fn parse_mtl(source: &str, path: &Path) -> Result<HashMap<String, Arc<dyn Material>>, LoadError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let mut tokens = line.split_whitespace();
+
+        let Some(keyword) = tokens.next() else { continue };
+        let arguments: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material.to_material());
+            }
+            current = Some((arguments.join(" "), MtlMaterial::default()));
+            continue;
+        }
+
+        let Some((_, material)) = current.as_mut() else {
+            continue;
+        };
+
+        match keyword {
+            "Kd" => material.diffuse = parse_vector(&arguments, path, line_number)?,
+            "Ke" => material.emissive = parse_vector(&arguments, path, line_number)?,
+            "Ks" => material.specular = parse_vector(&arguments, path, line_number)?,
+            "Ns" => material.shininess = parse_floats(&arguments, 1, path, line_number)?[0],
+            "Ni" => material.refraction_index = parse_floats(&arguments, 1, path, line_number)?[0],
+            "d" => material.dissolve = parse_floats(&arguments, 1, path, line_number)?[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats(&arguments, 1, path, line_number)?[0],
+            "illum" => material.illumination = parse_floats(&arguments, 1, path, line_number)?[0] as i32,
+            _ => {}
+        }
+    }
+
+    if let Some((name, material)) = current {
+        materials.insert(name, material.to_material());
+    }
+
+    Ok(materials)
+}
+
+struct MtlMaterial {
+    diffuse: Colour,
+    specular: Colour,
+    emissive: Colour,
+    shininess: f64,
+    refraction_index: f64,
+    dissolve: f64,
+    illumination: i32
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            diffuse: Colour::new(0.8, 0.8, 0.8),
+            specular: Colour::new(0.0, 0.0, 0.0),
+            emissive: Colour::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            refraction_index: 1.0,
+            dissolve: 1.0,
+            illumination: 2
+        }
+    }
+}
+
+impl MtlMaterial {
+    fn to_material(&self) -> Arc<dyn Material> {
+        if !self.emissive.near_zero() {
+            return Arc::new(DiffuseLight::new(self.emissive));
+        }
+
+        let transparent = self.dissolve < 1.0 || matches!(self.illumination, 4 | 6 | 7 | 9);
+        if transparent {
+            let ir = if self.refraction_index > 1.0 { self.refraction_index } else { 1.5 };
+            return Arc::new(Dielectric::new(ir));
+        }
+
+        let brightest = |colour: Colour| colour.x.max(colour.y).max(colour.z);
+        let specular = brightest(self.specular);
+        if self.illumination == 3 || (specular > 0.0 && specular >= brightest(self.diffuse)) {
+            // Blinn-Phong exponent to an approximate roughness
+            let fuzz = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt();
+            return Arc::new(Metal::new(self.specular, fuzz));
+        }
+
+        Arc::new(Matte::new(self.diffuse))
+    }
+}
+
+fn parse_floats(arguments: &[&str], minimum: usize, path: &Path, line: usize) -> Result<Vec<f64>, LoadError> {
+    if arguments.len() < minimum {
+        return Err(LoadError::parse(path, line, format!("expected at least {} numbers", minimum)));
+    }
+
+    arguments.iter()
+        .map(|argument| argument.parse::<f64>()
+            .map_err(|_| LoadError::parse(path, line, format!("'{}' is not a number", argument))))
+        .collect()
+}
+
+fn parse_vector(arguments: &[&str], path: &Path, line: usize) -> Result<Vector3, LoadError> {
+    let values = parse_floats(arguments, 3, path, line)?;
+
+    Ok(Point3::new(values[0], values[1], values[2]))
+}
+
+// A face corner is `v`, `v/vt`, `v//vn` or `v/vt/vn`. Indices start at 1, negative ones count back
+// from the last element defined so far.
+fn parse_corner(
+    corner: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+    path: &Path,
+    line: usize
+) -> Result<(usize, Option<usize>, Option<usize>), LoadError> {
+    let mut parts = corner.split('/');
+
+    let position = resolve_index(parts.next().unwrap_or(""), position_count, "vertex", path, line)?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, uv_count, "texture coordinate", path, line)?)
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, normal_count, "normal", path, line)?)
+    };
+
+    Ok((position, uv, normal))
+}
+
+fn resolve_index(index: &str, count: usize, kind: &str, path: &Path, line: usize) -> Result<usize, LoadError> {
+    let value: i64 = index.parse()
+        .map_err(|_| LoadError::parse(path, line, format!("'{}' is not a valid {} index", index, kind)))?;
+
+    let resolved = if value < 0 { count as i64 + value } else { value - 1 };
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(LoadError::parse(path, line, format!("{} index {} is out of range ({} defined)", kind, value, count)));
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::f64::consts::PI;
+    use std::path::Path;
+    use std::sync::Arc;
+    use crate::hittable::HitRecord;
+    use crate::loaders::LoadError;
+    use crate::loaders::test_support::{default_material, expect_parse_error};
+    use crate::material::{Material, Metal};
+    use crate::mesh::TriangleMesh;
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+    use crate::vector3::{Colour, Point3, Vector3};
+    use super::{parse_mtl, parse_obj};
+
+    fn parse(source: &str) -> Result<TriangleMesh, LoadError> {
+        parse_obj(source, Path::new("test.obj"), default_material())
+    }
+
+    fn parse_error(source: &str) -> (usize, String) {
+        expect_parse_error(parse(source))
+    }
+
+    fn triangle_positions(mesh: &TriangleMesh) -> Vec<[Point3; 3]> {
+        let data = mesh.data();
+        data.triangles.iter().map(|triangle| triangle.map(|index| data.positions[index])).collect()
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn negative_indices_count_back_from_the_latest_vertex() {
+        // the second face's -1 is the vertex defined just before it, not the last in the file
+        let mesh = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nf -3 -2 -1\nv 0 1 0\nf 1 -2 -1\nv 5 5 5\n").unwrap();
+        let triangles = triangle_positions(&mesh);
+
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[0].map(|p| (p.x, p.y)), [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]);
+        assert_eq!(triangles[1].map(|p| (p.x, p.y)), [(0.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+    }
+
+    #[test]
+    fn polygons_become_fans_and_corners_become_vertices() {
+        let source = format!("{}vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1 4/4/1\n", SQUARE);
+        let mesh = parse(&source).unwrap();
+        let data = mesh.data();
+
+        assert_eq!(data.triangles.len(), 2);
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.normals.len(), 4);
+        assert_eq!(data.uvs[2], (1.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        for face in ["f 1 2 5", "f 0 1 2", "f -5 1 2", "f 1/3 2/1 3/1"] {
+            let source = format!("{}vt 0 0\n{}\n", SQUARE, face);
+            let (line, message) = parse_error(&source);
+
+            assert_eq!(line, 6, "{}", face);
+            assert!(message.contains("out of range"), "{}: {}", face, message);
+        }
+
+        let (line, message) = parse_error(&format!("{}f 1 2 x\n", SQUARE));
+        assert_eq!(line, 5);
+        assert!(message.contains("'x' is not a valid vertex index"));
+    }
+
+    #[test]
+    fn rejects_unknown_materials() {
+        let (line, message) = parse_error(&format!("{}usemtl missing\nf 1 2 3\n", SQUARE));
+
+        assert_eq!(line, 5);
+        assert!(message.contains("unknown material 'missing'"));
+    }
+
+    #[test]
+    fn reports_missing_material_libraries() {
+        let source = format!("mtllib no_such_library.mtl\n{}f 1 2 3\n", SQUARE);
+
+        let Err(LoadError::Io { path, .. }) = parse(&source) else {
+            panic!("expected the missing library to be reported");
+        };
+        assert!(path.ends_with("no_such_library.mtl"));
+    }
+
+    fn parse_materials(source: &str) -> HashMap<String, Arc<dyn Material>> {
+        parse_mtl(source, Path::new("test.mtl")).unwrap()
+    }
+
+    // a hit on a surface facing +z, by a ray coming straight down onto it
+    fn hit(material: &Arc<dyn Material>) -> (Ray, HitRecord) {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0));
+        let mut record = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0), 1.0, Arc::clone(material));
+        record.front_face = true;
+        (ray, record)
+    }
+
+    // There's no asking a material what it is, so tell them apart by how they behave: lights
+    // glow, glass only scatters into exact directions, and of the rest, only Matte has a cosine
+    // density. The colour is what the material tints light scattered straight back up.
+    fn describe(material: &Arc<dyn Material>) -> (&'static str, (f64, f64, f64)) {
+        let (ray, record) = hit(material);
+        if material.is_emissive() {
+            let colour = material.emitted(&ray, &record);
+            return ("light", (colour.x, colour.y, colour.z));
+        }
+        if material.is_specular() {
+            return ("glass", (1.0, 1.0, 1.0));
+        }
+
+        let up = Vector3::new(0.0, 0.0, 1.0);
+        let colour = material.eval(&ray, &record, up) / material.scattering_pdf(&ray, &record, up);
+        let slanted = Vector3::new(0.0, 80f64.to_radians().sin(), 80f64.to_radians().cos());
+        let cosine_density = (material.scattering_pdf(&ray, &record, slanted) - slanted.z / PI).abs() < 1e-9;
+
+        // dividing by the density leaves rounding error behind
+        let round = |value: f64| (value * 1e9).round() / 1e9;
+        (if cosine_density { "matte" } else { "metal" }, (round(colour.x), round(colour.y), round(colour.z)))
+    }
+
+    // Recovers a glass's index of refraction from the angle it bends a ray coming in at 45 degrees
+    fn refraction_index(material: &Arc<dyn Material>) -> f64 {
+        let incoming = Vector3::new(0.0, 1.0, -1.0);
+        let ray = Ray::new(Point3::new(0.0, -1.0, 1.0), incoming);
+        let (_, record) = hit(material);
+
+        // scatter sometimes reflects instead, so keep going until it refracts
+        let mut sampler = Sampler::new(1);
+        loop {
+            let (_, scattered) = material.scatter(&ray, &record, &mut sampler).unwrap();
+            let direction = scattered.direction.unit();
+            if direction.z < 0.0 {
+                return 45f64.to_radians().sin() / direction.y;
+            }
+        }
+    }
+
+    #[test]
+    fn mtl_blocks_map_onto_materials() {
+        let materials = parse_materials(concat!(
+            "# comment, and a property before any material\n",
+            "Kd 1 1 1\n",
+            "newmtl red\n",
+            "Kd 0.8 0.1 0.1\n",
+            "\n",
+            "newmtl steel\n",
+            "Kd 0.1 0.1 0.1\n",
+            "Ks 0.9 0.8 0.7\n",
+            "newmtl polished\n",
+            "illum 3\n",
+            "Kd 0.5 0.5 0.5\n",
+            "Ks 0.2 0.2 0.2\n",
+            "newmtl see through\n",
+            "Kd 0.5 0.5 0.5\n",
+            "d 0.4\n",
+            "newmtl lamp\n",
+            "Kd 0.5 0.5 0.5\n",
+            "Ks 1 1 1\n",
+            "d 0.5\n",
+            "Ke 4 3 2\n"
+        ));
+
+        assert_eq!(materials.len(), 5);
+        assert_eq!(describe(&materials["red"]), ("matte", (0.8, 0.1, 0.1)));
+        assert_eq!(describe(&materials["steel"]), ("metal", (0.9, 0.8, 0.7)));
+        assert_eq!(describe(&materials["polished"]), ("metal", (0.2, 0.2, 0.2)));
+        assert_eq!(describe(&materials["see through"]).0, "glass");
+        assert_eq!(describe(&materials["lamp"]), ("light", (4.0, 3.0, 2.0)));
+    }
+
+    #[test]
+    fn transparency_and_refraction_index() {
+        let materials = parse_materials(concat!(
+            "newmtl dissolved\nd 0.9\nNi 1.7\n",
+            "newmtl transparent\nTr 0.2\n",
+            "newmtl refracting\nillum 7\nNi 1.33\n",
+            "newmtl opaque\nd 1\nNi 1.7\n"
+        ));
+
+        assert!((refraction_index(&materials["dissolved"]) - 1.7).abs() < 1e-9);
+        // an index of 1 (or none at all) would be invisible, so glass gets 1.5
+        assert!((refraction_index(&materials["transparent"]) - 1.5).abs() < 1e-9);
+        assert!((refraction_index(&materials["refracting"]) - 1.33).abs() < 1e-9);
+        assert_eq!(describe(&materials["opaque"]).0, "matte");
+    }
+
+    #[test]
+    fn metal_fuzz_comes_from_the_specular_exponent() {
+        let materials = parse_materials("newmtl rough\nKs 1 1 1\nNs 0\nnewmtl shiny\nKs 1 1 1\nNs 98\n");
+
+        for (name, shininess) in [("rough", 0.0), ("shiny", 98.0)] {
+            let fuzz = (2.0f64 / (shininess + 2.0)).sqrt();
+            let expected: Arc<dyn Material> = Arc::new(Metal::new(Colour::new(1.0, 1.0, 1.0), fuzz));
+            let (ray, record) = hit(&materials[name]);
+
+            // the densities match a metal of that fuzz everywhere, which they wouldn't for any other
+            for direction in [Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.05, 0.1, 1.0), Vector3::new(0.3, -0.2, 1.0), Vector3::new(0.8, 0.0, 0.6)] {
+                let actual = materials[name].scattering_pdf(&ray, &record, direction);
+                assert_eq!(actual, expected.scattering_pdf(&ray, &record, direction), "{} towards {:?}", name, (direction.x, direction.y, direction.z));
+            }
+        }
+    }
+
+    #[test]
+    fn reports_malformed_mtl_lines() {
+        let (line, message) = expect_parse_error(parse_mtl("newmtl a\nKd 1 1 1\nNs shiny\n", Path::new("test.mtl")));
+        assert_eq!(line, 3);
+        assert!(message.contains("'shiny' is not a number"), "{}", message);
+
+        let (line, _) = expect_parse_error(parse_mtl("newmtl a\nKd 1 1\n", Path::new("test.mtl")));
+        assert_eq!(line, 2);
+    }
+}
@@ -0,0 +1,413 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use crate::loaders::LoadError;
+use crate::material::Material;
+use crate::mesh::{MeshData, TriangleMesh};
+use crate::vector3::{Colour, Point3, Vector3};
+
+// Loads an ASCII or binary (either endianness) PLY file as a mesh. Vertex normals, colours and
+// texture coordinates are kept when the file has them, polygons are split into triangle fans and
+// any other elements are skipped.
+pub fn load_ply(path: &Path, material: Arc<dyn Material>) -> Result<TriangleMesh, LoadError> {
+    let bytes = fs::read(path).map_err(|error| LoadError::io(path, error))?;
+
+    parse_ply(&bytes, path, material)
+}
+
+// Parses the contents of a PLY file, where path is only used for error messages
+pub fn parse_ply(bytes: &[u8], path: &Path, material: Arc<dyn Material>) -> Result<TriangleMesh, LoadError> {
+    let (header, body_start) = parse_header(bytes, path)?;
+
+    let mut reader = match header.format {
+        Format::Ascii => {
+            let body = String::from_utf8_lossy(&bytes[body_start..]);
+            Reader::Ascii { tokens: body.split_whitespace().map(str::to_string).collect(), next: 0 }
+        }
+        Format::BinaryLittleEndian => Reader::Binary { bytes: &bytes[body_start..], next: 0, little_endian: true },
+        Format::BinaryBigEndian => Reader::Binary { bytes: &bytes[body_start..], next: 0, little_endian: false }
+    };
+
+    let mut data = MeshData::default();
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(element, &mut reader, &mut data, path)?,
+            "face" => read_faces(element, &mut reader, &mut data, path)?,
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        read_property(property, &mut reader, path)?;
+                    }
+                }
+            }
+        }
+    }
+
+    TriangleMesh::new(data, vec![material]).map_err(|error| LoadError::mesh(path, error))
+}
+
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[derive(Clone, Copy)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<ScalarType> {
+        match name {
+            "char" | "int8" => Some(ScalarType::Int8),
+            "uchar" | "uint8" => Some(ScalarType::UInt8),
+            "short" | "int16" => Some(ScalarType::Int16),
+            "ushort" | "uint16" => Some(ScalarType::UInt16),
+            "int" | "int32" => Some(ScalarType::Int32),
+            "uint" | "uint32" => Some(ScalarType::UInt32),
+            "float" | "float32" => Some(ScalarType::Float32),
+            "double" | "float64" => Some(ScalarType::Float64),
+            _ => None
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8
+        }
+    }
+
+    // largest value of an integer type, used to normalise colours to [0, 1]
+    fn colour_scale(self) -> f64 {
+        match self {
+            ScalarType::UInt8 | ScalarType::Int8 => 255.0,
+            ScalarType::UInt16 | ScalarType::Int16 => 65535.0,
+            ScalarType::UInt32 | ScalarType::Int32 => u32::MAX as f64,
+            ScalarType::Float32 | ScalarType::Float64 => 1.0
+        }
+    }
+}
+
+enum Property {
+    Scalar { name: String, value_type: ScalarType },
+    List { name: String, count_type: ScalarType, item_type: ScalarType }
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar { name, .. } | Property::List { name, .. } => name
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>
+}
+
+// Parses the text header and returns it with the offset of the first byte after end_header
+fn parse_header(bytes: &[u8], path: &Path) -> Result<(Header, usize), LoadError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut offset = 0;
+    let mut line_number = 0;
+
+    loop {
+        let Some(length) = bytes[offset..].iter().position(|&byte| byte == b'\n') else {
+            return Err(LoadError::parse(path, line_number + 1, "header has no end_header line"));
+        };
+
+        let line = String::from_utf8_lossy(&bytes[offset..offset + length]).trim().to_string();
+        offset += length + 1;
+        line_number += 1;
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if line_number == 1 {
+            if line != "ply" {
+                return Err(LoadError::parse(path, 1, "not a PLY file"));
+            }
+            continue;
+        }
+
+        match tokens.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(LoadError::parse(path, line_number, format!("unknown format '{}'", name)))
+                });
+            }
+            ["element", name, count] => {
+                let count = count.parse()
+                    .map_err(|_| LoadError::parse(path, line_number, format!("'{}' is not a valid element count", count)))?;
+                elements.push(Element { name: name.to_string(), count, properties: vec![] });
+            }
+            ["property", "list", count_type, item_type, name] => {
+                let property = Property::List {
+                    name: name.to_string(),
+                    count_type: scalar_type(count_type, path, line_number)?,
+                    item_type: scalar_type(item_type, path, line_number)?
+                };
+                current_element(&mut elements, path, line_number)?.properties.push(property);
+            }
+            ["property", value_type, name] => {
+                let property = Property::Scalar { name: name.to_string(), value_type: scalar_type(value_type, path, line_number)? };
+                current_element(&mut elements, path, line_number)?.properties.push(property);
+            }
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(LoadError::parse(path, line_number, format!("unexpected header line '{}'", line)))
+        }
+    }
+
+    let Some(format) = format else {
+        return Err(LoadError::parse(path, line_number, "header has no format line"));
+    };
+
+    Ok((Header { format, elements }, offset))
+}
+
+fn scalar_type(name: &str, path: &Path, line: usize) -> Result<ScalarType, LoadError> {
+    ScalarType::parse(name).ok_or_else(|| LoadError::parse(path, line, format!("unknown property type '{}'", name)))
+}
+
+fn current_element<'a>(elements: &'a mut [Element], path: &Path, line: usize) -> Result<&'a mut Element, LoadError> {
+    elements.last_mut().ok_or_else(|| LoadError::parse(path, line, "property defined before any element"))
+}
+
+// Body values, read one at a time in header order whatever the encoding
+enum Reader<'a> {
+    Ascii { tokens: Vec<String>, next: usize },
+    Binary { bytes: &'a [u8], next: usize, little_endian: bool }
+}
+
+impl Reader<'_> {
+    fn read(&mut self, value_type: ScalarType, path: &Path) -> Result<f64, LoadError> {
+        match self {
+            Reader::Ascii { tokens, next } => {
+                let token = tokens.get(*next).ok_or_else(|| LoadError::format(path, "file ends before all elements were read"))?;
+                *next += 1;
+
+                token.parse().map_err(|_| LoadError::format(path, format!("'{}' is not a number", token)))
+            }
+            Reader::Binary { bytes, next, little_endian } => {
+                let size = value_type.size();
+                let Some(raw) = bytes.get(*next..*next + size) else {
+                    return Err(LoadError::format(path, "file ends before all elements were read"));
+                };
+                *next += size;
+
+                let mut buffer = [0u8; 8];
+                buffer[..size].copy_from_slice(raw);
+                if !*little_endian {
+                    buffer[..size].reverse();
+                }
+
+                Ok(match value_type {
+                    ScalarType::Int8 => buffer[0] as i8 as f64,
+                    ScalarType::UInt8 => buffer[0] as f64,
+                    ScalarType::Int16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    ScalarType::UInt16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    ScalarType::Int32 => i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    ScalarType::UInt32 => u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    ScalarType::Float32 => f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    ScalarType::Float64 => f64::from_le_bytes(buffer)
+                })
+            }
+        }
+    }
+}
+
+// Reads every value of a property, lists included
+fn read_property(property: &Property, reader: &mut Reader, path: &Path) -> Result<Vec<f64>, LoadError> {
+    match property {
+        Property::Scalar { value_type, .. } => Ok(vec![reader.read(*value_type, path)?]),
+        // the count isn't trusted to size anything, a bad one just runs off the end of the file
+        Property::List { count_type, item_type, .. } => {
+            let count = reader.read(*count_type, path)? as usize;
+            let mut values = vec![];
+            for _ in 0..count {
+                values.push(reader.read(*item_type, path)?);
+            }
+            Ok(values)
+        }
+    }
+}
+
+fn read_vertices(element: &Element, reader: &mut Reader, data: &mut MeshData, path: &Path) -> Result<(), LoadError> {
+    let find = |names: &[&str]| element.properties.iter().position(|property| names.contains(&property.name()));
+
+    let (Some(x), Some(y), Some(z)) = (find(&["x"]), find(&["y"]), find(&["z"])) else {
+        return Err(LoadError::format(path, "vertex element needs x, y and z properties"));
+    };
+    let normal = find(&["nx"]).zip(find(&["ny"])).zip(find(&["nz"]));
+    let colour = find(&["red", "r", "diffuse_red"]).zip(find(&["green", "g", "diffuse_green"])).zip(find(&["blue", "b", "diffuse_blue"]));
+    let uv = find(&["u", "s", "texture_u", "texture_s"]).zip(find(&["v", "t", "texture_v", "texture_t"]));
+
+    let colour_scale = colour.map_or(1.0, |((red, _), _)| match &element.properties[red] {
+        Property::Scalar { value_type, .. } => value_type.colour_scale(),
+        Property::List { .. } => 1.0
+    });
+
+    for _ in 0..element.count {
+        let mut values = vec![];
+        for property in &element.properties {
+            values.push(read_property(property, reader, path)?.first().copied().unwrap_or(0.0));
+        }
+
+        data.positions.push(Point3::new(values[x], values[y], values[z]));
+        if let Some(((nx, ny), nz)) = normal {
+            data.normals.push(Vector3::new(values[nx], values[ny], values[nz]));
+        }
+        if let Some(((red, green), blue)) = colour {
+            data.colours.push(Colour::new(values[red], values[green], values[blue]) / colour_scale);
+        }
+        if let Some((u, v)) = uv {
+            data.uvs.push((values[u], values[v]));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_faces(element: &Element, reader: &mut Reader, data: &mut MeshData, path: &Path) -> Result<(), LoadError> {
+    let Some(indices_property) = element.properties.iter()
+        .position(|property| matches!(property.name(), "vertex_indices" | "vertex_index")) else {
+        return Err(LoadError::format(path, "face element needs a vertex_indices property"));
+    };
+
+    for face in 0..element.count {
+        let mut indices = vec![];
+        for (index, property) in element.properties.iter().enumerate() {
+            let values = read_property(property, reader, path)?;
+            if index == indices_property {
+                indices = values;
+            }
+        }
+
+        if indices.len() < 3 {
+            return Err(LoadError::format(path, format!("face {} has fewer than 3 vertices", face)));
+        }
+
+        // out of range indices are caught when the mesh is built, but anything that isn't a
+        // whole number would otherwise be truncated into some other vertex's index
+        if let Some(&bad) = indices.iter().find(|&&index| index < 0.0 || index.fract() != 0.0) {
+            return Err(LoadError::format(path, format!("face {} has invalid vertex index {}", face, bad)));
+        }
+
+        let indices: Vec<usize> = indices.iter().map(|&index| index as usize).collect();
+        for i in 1..indices.len() - 1 {
+            data.triangles.push([indices[0], indices[i], indices[i + 1]]);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::loaders::LoadError;
+    use crate::loaders::test_support::{default_material, expect_format_error};
+    use crate::mesh::TriangleMesh;
+    use super::parse_ply;
+
+    fn parse(bytes: &[u8]) -> Result<TriangleMesh, LoadError> {
+        parse_ply(bytes, Path::new("test.ply"), default_material())
+    }
+
+    fn header(format: &str, faces: usize) -> String {
+        format!(
+            "ply\nformat {} 1.0\ncomment a test\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+             property float nx\nproperty float ny\nproperty float nz\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+             element face {}\nproperty list uchar int vertex_indices\nend_header\n",
+            format, faces
+        )
+    }
+
+    const VERTICES: &str = "0 0 0 0 0 1 255 0 0\n1 0 0 0 0 1 0 255 0\n0 1 0 0 0 1 0 0 51\n";
+
+    #[test]
+    fn reads_ascii_normals_and_colours() {
+        let source = format!("{}{}3 0 1 2\n", header("ascii", 1), VERTICES);
+        let mesh = parse(source.as_bytes()).unwrap();
+        let data = mesh.data();
+
+        assert_eq!(data.triangles, vec![[0, 1, 2]]);
+        assert_eq!(data.normals.len(), 3);
+        assert_eq!(data.normals[1].z, 1.0);
+        assert_eq!(data.colours[0].x, 1.0);
+        assert_eq!(data.colours[2].z, 0.2);
+    }
+
+    #[test]
+    fn binary_matches_ascii() {
+        let mut bytes = header("binary_little_endian", 1).into_bytes();
+        for vertex in VERTICES.lines() {
+            let values: Vec<f32> = vertex.split_whitespace().map(|value| value.parse().unwrap()).collect();
+            for value in &values[..6] {
+                bytes.extend(value.to_le_bytes());
+            }
+            bytes.extend(values[6..].iter().map(|&value| value as u8));
+        }
+        bytes.push(3);
+        for index in [0i32, 1, 2] {
+            bytes.extend(index.to_le_bytes());
+        }
+
+        let binary = parse(&bytes).unwrap();
+        let ascii = parse(format!("{}{}3 0 1 2\n", header("ascii", 1), VERTICES).as_bytes()).unwrap();
+
+        assert_eq!(binary.data().triangles, ascii.data().triangles);
+        for (a, b) in binary.data().colours.iter().zip(&ascii.data().colours) {
+            assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+        }
+        for (a, b) in binary.data().positions.iter().zip(&ascii.data().positions) {
+            assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+        }
+    }
+
+    #[test]
+    fn rejects_negative_and_fractional_indices() {
+        for face in ["3 0 -1 2", "3 0 1.5 2"] {
+            let source = format!("{}{}{}\n", header("ascii", 1), VERTICES, face);
+            let message = expect_format_error(parse(source.as_bytes()));
+
+            assert!(message.contains("invalid vertex index"), "{}: {}", face, message);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices_and_short_files() {
+        let source = format!("{}{}3 0 1 3\n", header("ascii", 1), VERTICES);
+        assert!(matches!(parse(source.as_bytes()), Err(LoadError::Mesh { .. })));
+
+        let source = format!("{}{}3 0 1 2\n", header("ascii", 2), VERTICES);
+        expect_format_error(parse(source.as_bytes()));
+
+        // a face list running past the end of the file
+        let mut bytes = header("binary_little_endian", 1).into_bytes();
+        bytes.extend([0u8; 3 * 27]);
+        bytes.push(255);
+        expect_format_error(parse(&bytes));
+    }
+}
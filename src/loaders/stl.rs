@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use crate::loaders::LoadError;
+use crate::material::Material;
+use crate::mesh::{MeshData, TriangleMesh};
+use crate::vector3::Point3;
+
+// Loads an ASCII or binary STL file as a mesh. STL stores every triangle with its own copy of its
+// corners, so identical positions are merged back into shared vertices. The facet normals are
+// ignored since they're just the geometric normals the mesh computes anyway.
+pub fn load_stl(path: &Path, material: Arc<dyn Material>) -> Result<TriangleMesh, LoadError> {
+    let bytes = fs::read(path).map_err(|error| LoadError::io(path, error))?;
+
+    parse_stl(&bytes, path, material)
+}
+
+// Parses the contents of an STL file, where path is only used for error messages
+pub fn parse_stl(bytes: &[u8], path: &Path, material: Arc<dyn Material>) -> Result<TriangleMesh, LoadError> {
+    let triangles = if is_binary(bytes) {
+        read_binary(bytes, path)?
+    } else {
+        read_ascii(&String::from_utf8_lossy(bytes), path)?
+    };
+
+    let mut data = MeshData::default();
+    let mut vertices: HashMap<[u64; 3], usize> = HashMap::new();
+    for triangle in triangles {
+        let mut indices = [0; 3];
+        for (corner, point) in triangle.iter().enumerate() {
+            let key = [point.x.to_bits(), point.y.to_bits(), point.z.to_bits()];
+            indices[corner] = *vertices.entry(key).or_insert_with(|| {
+                data.positions.push(*point);
+                data.positions.len() - 1
+            });
+        }
+        data.triangles.push(indices);
+    }
+
+    TriangleMesh::new(data, vec![material]).map_err(|error| LoadError::mesh(path, error))
+}
+
+// A binary file is an 80 byte header, a 4 byte triangle count and 50 bytes per triangle. ASCII
+// files start with "solid", but binary headers may too, so those only count as binary when the
+// size matches the count exactly.
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() < 84 {
+        return false;
+    }
+
+    !bytes.trim_ascii_start().starts_with(b"solid") || bytes.len() as u64 == binary_size(binary_count(bytes))
+}
+
+fn binary_count(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]])
+}
+
+fn binary_size(count: u32) -> u64 {
+    84 + count as u64 * 50
+}
+
+fn read_binary(bytes: &[u8], path: &Path) -> Result<Vec<[Point3; 3]>, LoadError> {
+    let count = binary_count(bytes);
+    if bytes.len() as u64 != binary_size(count) {
+        return Err(LoadError::format(path, format!("file is {} bytes but the header says {} triangles ({} bytes)",
+            bytes.len(), count, binary_size(count))));
+    }
+    if count == 0 {
+        return Err(LoadError::format(path, "file has no triangles"));
+    }
+
+    let count = count as usize;
+    let float = |offset: usize| f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]) as f64;
+
+    let mut triangles = Vec::with_capacity(count);
+    for triangle in 0..count {
+        // skip the 12 byte normal, the trailing 2 byte attribute count is after the corners
+        let start = 84 + triangle * 50 + 12;
+        let corner = |n: usize| Point3::new(float(start + n * 12), float(start + n * 12 + 4), float(start + n * 12 + 8));
+
+        let corners = [corner(0), corner(1), corner(2)];
+        if corners.iter().any(|point| !(point.x.is_finite() && point.y.is_finite() && point.z.is_finite())) {
+            return Err(LoadError::format(path, format!("triangle {} has a non-finite vertex", triangle)));
+        }
+        triangles.push(corners);
+    }
+
+    Ok(triangles)
+}
+
+fn read_ascii(source: &str, path: &Path) -> Result<Vec<[Point3; 3]>, LoadError> {
+    if source.split_whitespace().next() != Some("solid") {
+        return Err(LoadError::format(path, "not an STL file, it neither starts with 'solid' nor has a binary header"));
+    }
+
+    let mut triangles = vec![];
+    let mut corners = vec![];
+    // the line of the facet being read, if any
+    let mut facet = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.first() {
+            Some(&"facet") => {
+                if facet.is_some() {
+                    return Err(LoadError::parse(path, line_number, "facet starts before the previous one ends"));
+                }
+                facet = Some(line_number);
+            }
+            Some(&"vertex") => {
+                if tokens.len() != 4 {
+                    return Err(LoadError::parse(path, line_number, "a vertex needs 3 coordinates"));
+                }
+
+                let mut coordinates = [0.0; 3];
+                for (coordinate, token) in coordinates.iter_mut().zip(&tokens[1..]) {
+                    *coordinate = token.parse()
+                        .map_err(|_| LoadError::parse(path, line_number, format!("'{}' is not a number", token)))?;
+                }
+                corners.push(Point3::new(coordinates[0], coordinates[1], coordinates[2]));
+            }
+            Some(&"endfacet") => {
+                if corners.len() != 3 {
+                    return Err(LoadError::parse(path, line_number, format!("facet has {} vertices instead of 3", corners.len())));
+                }
+                triangles.push([corners[0], corners[1], corners[2]]);
+                corners.clear();
+                facet = None;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(line_number) = facet {
+        return Err(LoadError::parse(path, line_number, "facet has no endfacet"));
+    }
+    if triangles.is_empty() {
+        return Err(LoadError::format(path, "file has no facets"));
+    }
+
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::loaders::LoadError;
+    use crate::loaders::test_support::{default_material, expect_format_error, expect_parse_error};
+    use crate::mesh::TriangleMesh;
+    use super::parse_stl;
+
+    fn parse(bytes: &[u8]) -> Result<TriangleMesh, LoadError> {
+        parse_stl(bytes, Path::new("test.stl"), default_material())
+    }
+
+    // two triangles sharing an edge, making a unit square
+    const SQUARE: [[[f32; 3]; 3]; 2] = [
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+        [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]]
+    ];
+
+    fn ascii() -> String {
+        let mut source = "solid square\n".to_string();
+        for triangle in SQUARE {
+            source += "  facet normal 0 0 1\n    outer loop\n";
+            for [x, y, z] in triangle {
+                source += &format!("      vertex {} {} {}\n", x, y, z);
+            }
+            source += "    endloop\n  endfacet\n";
+        }
+
+        source + "endsolid square\n"
+    }
+
+    // the header deliberately starts with "solid", as some exporters' do
+    fn binary() -> Vec<u8> {
+        let mut bytes = b"solid but actually binary".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend((SQUARE.len() as u32).to_le_bytes());
+
+        for triangle in SQUARE {
+            bytes.extend([0.0f32, 0.0, 1.0].iter().flat_map(|value| value.to_le_bytes()));
+            bytes.extend(triangle.iter().flatten().flat_map(|value| value.to_le_bytes()));
+            bytes.extend([0, 0]);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn ascii_and_binary_give_the_same_mesh() {
+        let ascii = parse(ascii().as_bytes()).unwrap();
+        let binary = parse(&binary()).unwrap();
+
+        for mesh in [&ascii, &binary] {
+            // the corners the triangles share are merged
+            assert_eq!(mesh.data().positions.len(), 4);
+            assert_eq!(mesh.data().triangles.len(), 2);
+        }
+
+        assert_eq!(ascii.data().triangles, binary.data().triangles);
+        for (a, b) in ascii.data().positions.iter().zip(&binary.data().positions) {
+            assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+        }
+    }
+
+    #[test]
+    fn reports_malformed_ascii_facets() {
+        let source = ascii().replacen("      vertex 1 1 0\n", "", 1);
+
+        let (line, message) = expect_parse_error(parse(source.as_bytes()));
+
+        assert_eq!(line, 7);
+        assert!(message.contains("2 vertices"), "{}", message);
+
+        let source = ascii().replacen("vertex 1 0 0", "vertex 1 zero 0", 1);
+        assert_eq!(expect_parse_error(parse(source.as_bytes())).0, 5);
+    }
+
+    #[test]
+    fn rejects_non_finite_binary_vertices() {
+        let mut bytes = binary();
+        bytes[84 + 12..84 + 16].copy_from_slice(&f32::NAN.to_le_bytes());
+
+        expect_format_error(parse(&bytes));
+    }
+
+    #[test]
+    fn binary_size_must_match_the_triangle_count() {
+        // without "solid" at the start the file can only be binary
+        let mut bytes = binary();
+        bytes[..5].copy_from_slice(b"mesh ");
+        assert_eq!(parse(&bytes).unwrap().data().triangles.len(), 2);
+
+        bytes.extend([0; 10]);
+        let message = expect_format_error(parse(&bytes));
+        assert!(message.contains("2 triangles"), "{}", message);
+
+        bytes.truncate(84 + 50);
+        expect_format_error(parse(&bytes));
+
+        // with it, a mismatched size means the file is read as ASCII, where it has no facets
+        let mut bytes = binary();
+        bytes.extend([0; 10]);
+        expect_format_error(parse(&bytes));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_stl() {
+        for bytes in [&b""[..], b"   \n", b"hello world\n", b"facet normal 0 0 1\n"] {
+            expect_format_error(parse(bytes));
+        }
+    }
+
+    #[test]
+    fn reports_unterminated_facets() {
+        let source = ascii().replace("  endfacet\nendsolid square\n", "");
+
+        let (line, message) = expect_parse_error(parse(source.as_bytes()));
+
+        assert_eq!(line, 9);
+        assert!(message.contains("endfacet"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_files_without_triangles() {
+        expect_format_error(parse(b"solid empty\nendsolid empty\n"));
+
+        let mut bytes = binary();
+        bytes.truncate(84);
+        bytes[80..84].copy_from_slice(&0u32.to_le_bytes());
+        expect_format_error(parse(&bytes));
+    }
+}
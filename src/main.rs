@@ -1,87 +1,200 @@
-mod vector3;
-mod ray;
-mod camera;
-mod hittable;
-mod sphere;
-mod hittable_list;
-mod interval;
-mod util;
-mod material;
-
-use std::sync::Arc;
-use crate::camera::Camera;
-use crate::hittable::{Hittable};
-use crate::hittable_list::HittableList;
-use crate::interval::Interval;
-use crate::material::{Dielectric, Matte, Metal};
-use crate::sphere::Sphere;
-use crate::vector3::{Colour, Point3, Vector3};
-
-fn random_scene() -> HittableList {
-    let mut world = HittableList::new();
-
-    let ground_material = Matte::new(Colour::new(0.5, 0.5, 0.5));
-    let sphere = Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Arc::new(ground_material),
-    );
-    world.add(Box::new(sphere));
-
-    let some_point = Point3::new(4.0, 0.2, 0.0);
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = util::random();
-            let center = Point3::new(a as f64 + 0.9 * util::random(), 0.2, b as f64 + 0.9 * util::random());
-
-            if (center - some_point).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = vector3::random() * vector3::random();
-                    let sphere_material = Matte::new(albedo);
-                    let sphere = Sphere::new(center, 0.2, Arc::new(sphere_material));
-                    world.add(Box::new(sphere));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = vector3::random_in_interval(Interval::new(0.0, 0.5));
-                    let fuzz = util::random_in_interval(Interval::new(0.0, 0.5));
-                    let sphere_material = Metal::new(albedo, fuzz);
-                    let sphere = Sphere::new(center, 0.2, Arc::new(sphere_material));
-                    world.add(Box::new(sphere));
-                } else {
-                    // glass
-                    let sphere_material = Dielectric::new(1.5);
-                    let sphere = Sphere::new(center, 0.2, Arc::new(sphere_material));
-                    world.add(Box::new(sphere));
-                }
-            }
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use in1weekend::{BvhNode, Camera, Lights, Scene, Vector3};
+use in1weekend::loaders::gltf::load_gltf;
+use in1weekend::output::ImageFormat;
+use in1weekend::output::tonemap::{ToneMapOperator, ToneMapping};
+use in1weekend::scene::builtin::{builtin_scene, BUILTIN_SCENES};
+use in1weekend::scene::file::load_scene;
+
+/// Path traces a scene and writes the image to a file
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Scene to render: a built-in scene name, a .toml scene file or a .gltf/.glb file
+    #[arg(short = 'S', long, default_value = "random")]
+    scene: String,
+
+    /// Where to write the image
+    #[arg(short, long, default_value = "scene.ppm")]
+    output: PathBuf,
+
+    /// Image format (p3, ppm, png, png16, pfm, exr, exr32), guessed from the output extension if
+    /// not given
+    #[arg(short, long)]
+    format: Option<ImageFormat>,
+
+    /// Image width in pixels
+    #[arg(short = 'W', long)]
+    width: Option<u32>,
+
+    /// Image height in pixels, changing the aspect ratio if the width is also given
+    #[arg(short = 'H', long)]
+    height: Option<u32>,
+
+    /// Samples per pixel
+    #[arg(short, long)]
+    samples: Option<u32>,
+
+    /// Maximum number of bounces per path
+    #[arg(short = 'd', long)]
+    max_depth: Option<u32>,
+
+    /// Seed for the renderer and for randomly generated scenes
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Worker threads, 0 for one per core
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// Camera position, as x,y,z
+    #[arg(long, value_parser = parse_vector, allow_hyphen_values = true)]
+    look_from: Option<Vector3>,
+
+    /// Point the camera looks at, as x,y,z
+    #[arg(long, value_parser = parse_vector, allow_hyphen_values = true)]
+    look_at: Option<Vector3>,
+
+    /// Camera up direction, as x,y,z
+    #[arg(long, value_parser = parse_vector, allow_hyphen_values = true)]
+    vup: Option<Vector3>,
+
+    /// Vertical field of view in degrees
+    #[arg(long)]
+    vfov: Option<f64>,
+
+    /// Angle of the defocus cone in degrees, 0 for no depth of field
+    #[arg(long)]
+    defocus_angle: Option<f64>,
+
+    /// Distance to the plane in perfect focus
+    #[arg(long)]
+    focus_dist: Option<f64>,
+
+    /// Tone mapping operator (clamp, reinhard, extended-reinhard, aces, hable)
+    #[arg(short, long, default_value = "clamp")]
+    tone_map: ToneMapOperator,
+
+    /// Exposure adjustment in stops
+    #[arg(short, long, default_value_t = 0.0, allow_hyphen_values = true)]
+    exposure: f64
+}
+
+fn parse_vector(text: &str) -> Result<Vector3, String> {
+    let components: Vec<f64> = text
+        .split(',')
+        .map(|component| component.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|error| format!("{} in '{}'", error, text))?;
+
+    match components[..] {
+        [x, y, z] => Ok(Vector3::new(x, y, z)),
+        _ => Err(format!("expected three comma-separated numbers, not '{}'", text))
+    }
+}
+
+// Built-in names win over files, anything else is loaded according to its extension
+fn load(name: &str, seed: u64) -> Result<Scene, String> {
+    if let Some(scene) = builtin_scene(name, seed) {
+        return Ok(scene);
+    }
+
+    let path = Path::new(name);
+    if !path.exists() {
+        return Err(format!("'{}' is neither a file nor a built-in scene ({})", name, BUILTIN_SCENES.join(", ")));
+    }
+
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_ascii_lowercase();
+    match extension.as_str() {
+        "gltf" | "glb" => {
+            let scene = load_gltf(path).map_err(|error| error.to_string())?;
+            let camera = scene.cameras.first().copied().unwrap_or_default();
+
+            Ok(Scene { world: scene.world, lights: Lights { area: scene.lights, analytic: vec![] }, camera })
         }
+        _ => load_scene(path).map_err(|error| error.to_string())
     }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    // work out the format before spending time on rendering
+    let format = match args.format {
+        Some(format) => format,
+        None => ImageFormat::from_path(&args.output).ok_or_else(|| {
+            format!("can't tell the image format of {}, use --format", args.output.display())
+        })?
+    };
 
-    let sphere_material = Matte::new(Colour::new(0.9, 0.2, 0.3));
-    let sphere = Sphere::new(Vector3::new(-4.0, 1.0, 0.0), 1.0, Arc::new(sphere_material));
-    world.add(Box::new(sphere));
+    let scene = load(&args.scene, args.seed)?;
 
-    let sphere_material = Dielectric::new(1.5);
-    let sphere = Sphere::new(Vector3::new(0.0, 1.0, 0.0), 1.0, Arc::new(sphere_material));
-    world.add(Box::new(sphere));
+    let mut settings = scene.camera;
+    settings.seed = args.seed;
+    settings.auxiliary_layers = format.stores_auxiliary_layers();
 
-    let sphere_material = Metal::new(Colour::new(0.7, 0.6, 0.5), 0.0);
-    let sphere = Sphere::new(Vector3::new(4.0, 1.0, 0.0), 1.0, Arc::new(sphere_material));
-    world.add(Box::new(sphere));
+    if let Some(threads) = args.threads { settings.threads = threads; }
+    if let Some(samples) = args.samples { settings.samples_per_pixel = samples; }
+    if let Some(max_depth) = args.max_depth { settings.max_depth = max_depth; }
+    if let Some(look_from) = args.look_from { settings.look_from = look_from; }
+    if let Some(look_at) = args.look_at { settings.look_at = look_at; }
+    if let Some(vup) = args.vup { settings.vup = vup; }
+    if let Some(vfov) = args.vfov { settings.vfov = vfov; }
+    if let Some(defocus_angle) = args.defocus_angle { settings.defocus_angle = defocus_angle; }
+    if let Some(focus_dist) = args.focus_dist { settings.focus_dist = focus_dist; }
 
-    world
+    match (args.width, args.height) {
+        (Some(width), Some(height)) => {
+            settings.image_width = width;
+            settings.aspect_ratio = width as f64 / height as f64;
+        }
+        (Some(width), None) => settings.image_width = width,
+        (None, Some(height)) => {
+            // the width is rounded, so recompute the aspect ratio from it to keep the height exact
+            let width = (height as f64 * settings.aspect_ratio).round().max(1.0) as u32;
+            settings.image_width = width;
+            settings.aspect_ratio = width as f64 / height as f64;
+        }
+        (None, None) => {}
+    }
+
+    let camera = Camera::new(settings).map_err(|error| format!("invalid camera settings: {}", error))?;
+    let world = BvhNode::new(scene.world);
+    let framebuffer = camera.render_with_progress(&world, &scene.lights, |finished, total| {
+        eprint!("\rRendered tile {} of {}", finished, total);
+        if finished == total {
+            eprintln!();
+        }
+    });
+
+    let file = File::create(&args.output).map_err(|error| format!("could not create {}: {}", args.output.display(), error))?;
+    let tone_mapping = ToneMapping::new(args.tone_map, args.exposure);
+
+    format.writer(tone_mapping)
+        .write(&framebuffer, &mut BufWriter::new(file))
+        .map_err(|error| format!("could not write {}: {}", args.output.display(), error))?;
+
+    println!("Wrote {}", args.output.display());
+
+    Ok(())
 }
 
-fn main() {
-    //World
-    let world = random_scene();
+// Parses the arguments, listing the built-in scenes after the help so it can't fall out of date
+fn parse_args() -> Args {
+    let command = Args::command().after_help(format!("Built-in scenes: {}", BUILTIN_SCENES.join(", ")));
+    let mut matches = command.get_matches();
 
-    //create camera + render scene
-    let camera : Camera = Camera::new(
-        Point3::new(13.0, 2.0, 3.0), // look from
-        Point3::new(0.0, 0.0, 0.0) // look at
-    );
+    Args::from_arg_matches_mut(&mut matches).unwrap_or_else(|error| error.exit())
+}
 
-    camera.render(&world);
-}
\ No newline at end of file
+fn main() -> ExitCode {
+    match run(parse_args()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,19 +1,57 @@
-use std::ops::DivAssign;
+use std::f64::consts::PI;
+use std::sync::Arc;
 use crate::hittable::HitRecord;
-use crate::ray::Ray;
-use crate::util;
-use crate::vector3::{Colour, random_in_unit_sphere, random_unit_vector};
+use crate::ray::{Ray, RayDifferentials};
+use crate::sampler::Sampler;
+use crate::texture::{SolidColour, Texture};
+use crate::vector3::{Colour, random_in_unit_sphere, random_unit_vector, Vector3};
 
-pub trait Material {
-    fn scatter(&self, ray : &Ray, record:  &HitRecord) -> Option<(Colour, Ray)>;
+pub trait Material: Send + Sync {
+    fn scatter(&self, ray : &Ray, record:  &HitRecord, sampler : &mut Sampler) -> Option<(Colour, Ray)>;
+
+    // Light given off by the surface at the hit point towards where the ray came from. Most
+    // materials don't glow.
+    fn emitted(&self, _ray: &Ray, _record: &HitRecord) -> Colour {
+        Colour::new(0.0, 0.0, 0.0)
+    }
+
+    // whether emitted can ever be anything but black, so geometry using the material should be
+    // sampled as a light
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // Materials that scatter into a spread of directions can say how much light arriving from any
+    // given direction they send back along the ray, which lets the renderer sample lights directly
+    // and weigh those samples against the ones scatter picks.
+    //
+    // eval is the BSDF times the cosine of the angle to the normal, and scattering_pdf is the
+    // density (over solid angle) of scatter choosing the direction, so for a direction scatter
+    // picked its attenuation is eval / scattering_pdf. Materials that only ever scatter into one
+    // exact direction, like glass or a perfect mirror, are specular and use the defaults.
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    fn eval(&self, _ray: &Ray, _record: &HitRecord, _direction: Vector3) -> Colour {
+        Colour::new(0.0, 0.0, 0.0)
+    }
+
+    fn scattering_pdf(&self, _ray: &Ray, _record: &HitRecord, _direction: Vector3) -> f64 {
+        0.0
+    }
 }
 
 pub struct Matte {
-    albedo : Colour
+    albedo : Arc<dyn Texture>
 }
 
 impl Matte {
     pub fn new(albedo : Colour) -> Matte {
+        Matte::textured(Arc::new(SolidColour::new(albedo)))
+    }
+
+    pub fn textured(albedo : Arc<dyn Texture>) -> Matte {
         Matte {
             albedo
         }
@@ -21,12 +59,16 @@ impl Matte {
 }
 
 pub struct Metal {
-    albedo : Colour,
+    albedo : Arc<dyn Texture>,
     fuzz : f64
 }
 
 impl Metal {
     pub fn new(albedo : Colour, fuzz : f64) -> Metal {
+        Metal::textured(Arc::new(SolidColour::new(albedo)), fuzz)
+    }
+
+    pub fn textured(albedo : Arc<dyn Texture>, fuzz : f64) -> Metal {
         Metal {
             albedo,
             fuzz
@@ -54,10 +96,81 @@ impl Dielectric {
     }
 }
 
+// A light source: emits the same radiance in every direction from the front of the surface (the
+// side its normal points to) and nothing from the back, and absorbs whatever light hits it
+pub struct DiffuseLight {
+    emit : Colour
+}
+
+impl DiffuseLight {
+    pub fn new(emit : Colour) -> DiffuseLight {
+        DiffuseLight {
+            emit
+        }
+    }
+}
+
+// the albedo texture at the hit, multiplied by any vertex colour
+fn tint(albedo: &dyn Texture, record: &HitRecord) -> Colour {
+    let albedo = match &record.differentials {
+        Some(footprint) => albedo.filtered_value(record.u, record.v, record.point, footprint),
+        None => albedo.value(record.u, record.v, record.point)
+    };
+
+    match record.vertex_colour {
+        Some(colour) => albedo * colour,
+        None => albedo
+    }
+}
+
+// Ray differentials for a ray leaving the hit along the unit direction wi after a mirror
+// reflection (pbrt's specular reflection): the neighbouring rays leave from the neighbouring
+// points on the surface, reflected about the normal as it turns towards them. Only rays that
+// arrived with differentials leave with them.
+fn reflected_differentials(ray: &Ray, record: &HitRecord, wi: Vector3) -> Option<RayDifferentials> {
+    let (rays, surface) = (ray.differentials?, record.differentials?);
+    let n = record.normal;
+    let wo = -ray.direction.unit();
+
+    let neighbour = |dpd: Vector3, dndu_weight: f64, dndv_weight: f64, direction: Vector3| {
+        let dnd = record.dndu * dndu_weight + record.dndv * dndv_weight;
+        let dwod = -direction.unit() - wo;
+        let ddnd = dwod.dot(n) + wo.dot(dnd);
+
+        (record.point + dpd, wi - dwod + (dnd * wo.dot(n) + n * ddnd) * 2.0)
+    };
+    let (rx_origin, rx_direction) = neighbour(surface.dpdx, surface.dudx, surface.dvdx, rays.rx_direction);
+    let (ry_origin, ry_direction) = neighbour(surface.dpdy, surface.dudy, surface.dvdy, rays.ry_direction);
+
+    Some(RayDifferentials { rx_origin, rx_direction, ry_origin, ry_direction })
+}
+
+// The same for refraction into the unit direction wi, where refraction_ratio is the index of
+// refraction on the incoming side over the one on the far side
+fn refracted_differentials(ray: &Ray, record: &HitRecord, wi: Vector3, refraction_ratio: f64) -> Option<RayDifferentials> {
+    let (rays, surface) = (ray.differentials?, record.differentials?);
+    let n = record.normal;
+    let wo = -ray.direction.unit();
+    let eta = refraction_ratio;
+    let mu = eta * wo.dot(n) - wi.dot(n).abs();
+
+    let neighbour = |dpd: Vector3, dndu_weight: f64, dndv_weight: f64, direction: Vector3| {
+        let dnd = record.dndu * dndu_weight + record.dndv * dndv_weight;
+        let dwod = -direction.unit() - wo;
+        let ddnd = dwod.dot(n) + wo.dot(dnd);
+        let dmud = (eta - eta * eta * wo.dot(n) / wi.dot(n).abs()) * ddnd;
+
+        (record.point + dpd, wi - dwod * eta + dnd * mu + n * dmud)
+    };
+    let (rx_origin, rx_direction) = neighbour(surface.dpdx, surface.dudx, surface.dvdx, rays.rx_direction);
+    let (ry_origin, ry_direction) = neighbour(surface.dpdy, surface.dudy, surface.dvdy, rays.ry_direction);
+
+    Some(RayDifferentials { rx_origin, rx_direction, ry_origin, ry_direction })
+}
 
 impl Material for Matte {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Colour, Ray)> {
-        let mut scatter_direction = record.normal + random_unit_vector();
+    fn scatter(&self, _ray: &Ray, record: &HitRecord, sampler: &mut Sampler) -> Option<(Colour, Ray)> {
+        let mut scatter_direction = record.normal + random_unit_vector(sampler);
 
         if scatter_direction.near_zero() {
             scatter_direction = record.normal;
@@ -65,25 +178,81 @@ impl Material for Matte {
 
         let scattered = Ray::new(record.point, scatter_direction);
 
-        Some((self.albedo, scattered))
+        Some((tint(self.albedo.as_ref(), record), scattered))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, ray: &Ray, record: &HitRecord, direction: Vector3) -> Colour {
+        tint(self.albedo.as_ref(), record) * self.scattering_pdf(ray, record, direction)
+    }
+
+    // normal + a random unit vector is cosine weighted around the normal
+    fn scattering_pdf(&self, _ray: &Ray, record: &HitRecord, direction: Vector3) -> f64 {
+        let cosine = record.normal.dot(direction.unit());
+
+        if cosine > 0.0 { cosine / PI } else { 0.0 }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Colour, Ray)> {
+    fn scatter(&self, ray: &Ray, record: &HitRecord, sampler: &mut Sampler) -> Option<(Colour, Ray)> {
         let reflected = ray.direction.unit().reflect(&record.normal);
-        let scattered = Ray::new(record.point, reflected + random_in_unit_sphere() * self.fuzz);
+        let mut scattered = Ray::new(record.point, reflected + random_in_unit_sphere(sampler) * self.fuzz);
+
+        // fuzz only moves the direction, the footprint spreads as it would for a mirror
+        scattered.differentials = reflected_differentials(ray, record, scattered.direction.unit());
 
         if scattered.direction.dot(record.normal) > 0.0 {
-            Some((self.albedo, scattered))
+            Some((tint(self.albedo.as_ref(), record), scattered))
         } else {
             None
         }
     }
+
+    // with no fuzz there's only the mirror direction, and its density would be infinite
+    fn is_specular(&self) -> bool {
+        self.fuzz < 1e-4
+    }
+
+    fn eval(&self, ray: &Ray, record: &HitRecord, direction: Vector3) -> Colour {
+        tint(self.albedo.as_ref(), record) * self.scattering_pdf(ray, record, direction)
+    }
+
+    // scatter offsets the mirror direction r (a unit vector) by a point picked uniformly from a
+    // ball of radius fuzz around it. The density of a direction w is the volume of the ball along
+    // w, t^2 dt integrated between the two points t1 and t2 where t * w enters and leaves the
+    // ball, over the whole ball's volume: (t2^3 - t1^3) / (4 pi fuzz^3). Directions scatter
+    // rejects for going under the surface have no density.
+    fn scattering_pdf(&self, ray: &Ray, record: &HitRecord, direction: Vector3) -> f64 {
+        if self.is_specular() || direction.dot(record.normal) <= 0.0 {
+            return 0.0;
+        }
+
+        let reflected = ray.direction.unit().reflect(&record.normal);
+        let w = direction.unit();
+
+        // |t w - r|^2 = fuzz^2 as a quadratic in t, with |w| = |r| = 1
+        let half_b = w.dot(reflected);
+        let discriminant = half_b * half_b - (1.0 - self.fuzz * self.fuzz);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+
+        let t1 = (half_b - discriminant.sqrt()).max(0.0);
+        let t2 = half_b + discriminant.sqrt();
+        if t2 <= 0.0 {
+            return 0.0;
+        }
+
+        (t2.powi(3) - t1.powi(3)) / (4.0 * PI * self.fuzz.powi(3))
+    }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<(Colour, Ray)> {
+    fn scatter(&self, ray: &Ray, record: &HitRecord, sampler: &mut Sampler) -> Option<(Colour, Ray)> {
         let attenuation = Colour::new(1.0, 1.0, 1.0);
         let refraction_ratio = if record.front_face {
             1.0 / self.ir
@@ -97,13 +266,117 @@ impl Material for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction = if cannot_refract || (Dielectric::reflectance(cos_theta, refraction_ratio) > util::random()) {
-            unit_direction.reflect(&record.normal)
+        let (direction, differentials) = if cannot_refract || (Dielectric::reflectance(cos_theta, refraction_ratio) > sampler.random()) {
+            let direction = unit_direction.reflect(&record.normal);
+            (direction, reflected_differentials(ray, record, direction))
         } else {
-            unit_direction.refract(&record.normal, refraction_ratio)
+            let direction = unit_direction.refract(&record.normal, refraction_ratio);
+            (direction, refracted_differentials(ray, record, direction, refraction_ratio))
         };
 
-        let scattered = Ray::new(record.point, direction);
+        let scattered = Ray { origin: record.point, direction, differentials };
         Some((attenuation, scattered))
     }
-}
\ No newline at end of file
+}
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray: &Ray, _record: &HitRecord, _sampler: &mut Sampler) -> Option<(Colour, Ray)> {
+        None
+    }
+
+    fn emitted(&self, _ray: &Ray, record: &HitRecord) -> Colour {
+        if record.front_face { self.emit } else { Colour::new(0.0, 0.0, 0.0) }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::interval::Interval;
+    use crate::material::{DiffuseLight, Material, Matte, Metal};
+    use crate::quad::Quad;
+    use crate::ray::Ray;
+    use crate::vector3::{Colour, Point3, Vector3};
+
+    // a hit at the origin on a surface facing +z, by a ray coming in along incoming
+    fn hit(material: Arc<dyn Material>, incoming: Vector3) -> (Ray, HitRecord) {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0) - incoming, incoming);
+        let mut record = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0), 1.0, material);
+        record.front_face = true;
+        (ray, record)
+    }
+
+    // Midpoint rule over the hemisphere above the surface, in cos theta and phi, which are
+    // uniform in solid angle
+    fn integrate_hemisphere(f: impl Fn(Vector3) -> f64) -> f64 {
+        let steps = 1000;
+        let mut total = 0.0;
+        for i in 0..steps {
+            let cos_theta = (i as f64 + 0.5) / steps as f64;
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            for j in 0..steps {
+                let phi = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+                total += f(Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta));
+            }
+        }
+
+        total * 2.0 * PI / (steps * steps) as f64
+    }
+
+    #[test]
+    fn scattering_pdfs_integrate_to_one() {
+        let incoming = Vector3::new(0.3, -0.2, -1.0);
+        let materials: [Arc<dyn Material>; 3] = [
+            Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5))),
+            Arc::new(Metal::new(Colour::new(0.8, 0.8, 0.8), 0.3)),
+            Arc::new(Metal::new(Colour::new(0.8, 0.8, 0.8), 0.6))
+        ];
+
+        for material in materials {
+            let (ray, record) = hit(material, incoming);
+            let integral = integrate_hemisphere(|direction| record.material.scattering_pdf(&ray, &record, direction));
+
+            assert!((integral - 1.0).abs() < 0.01, "{}", integral);
+        }
+    }
+
+    #[test]
+    fn matte_eval_over_pdf_is_the_albedo() {
+        let (ray, record) = hit(Arc::new(Matte::new(Colour::new(0.7, 0.4, 0.1))), Vector3::new(0.0, 0.5, -1.0));
+
+        for direction in [Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.5, -0.3, 0.2), Vector3::new(-2.0, 1.0, 0.1)] {
+            let eval = record.material.eval(&ray, &record, direction);
+            let pdf = record.material.scattering_pdf(&ray, &record, direction);
+
+            assert!(pdf > 0.0);
+            let ratio = eval / pdf;
+            assert!((ratio.x - 0.7).abs() < 1e-12 && (ratio.y - 0.4).abs() < 1e-12 && (ratio.z - 0.1).abs() < 1e-12);
+        }
+
+        // nothing scatters below the surface
+        assert_eq!(record.material.scattering_pdf(&ray, &record, Vector3::new(0.0, 0.0, -1.0)), 0.0);
+    }
+
+    #[test]
+    fn lights_only_emit_from_the_front() {
+        // a unit square in the xy plane, facing +z
+        let emit = Colour::new(4.0, 2.0, 1.0);
+        let light = Quad::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), Arc::new(DiffuseLight::new(emit)));
+
+        let emitted = |origin: Point3, direction: Vector3| {
+            let ray = Ray::new(origin, direction);
+            let record = light.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+            let colour = record.material.emitted(&ray, &record);
+            (colour.x, colour.y, colour.z)
+        };
+
+        assert_eq!(emitted(Point3::new(0.5, 0.5, 1.0), Vector3::new(0.0, 0.0, -1.0)), (4.0, 2.0, 1.0));
+        assert_eq!(emitted(Point3::new(0.2, 0.7, 3.0), Vector3::new(0.1, -0.1, -1.0)), (4.0, 2.0, 1.0));
+        assert_eq!(emitted(Point3::new(0.5, 0.5, -1.0), Vector3::new(0.0, 0.0, 1.0)), (0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,398 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::triangle;
+use crate::vector3::*;
+
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+// Raw buffers for a mesh. Normals, colours and uvs are either empty or have one entry per
+// position, and material_ids is either empty (every triangle uses the first material) or has one
+// per triangle.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vector3>,
+    pub colours: Vec<Colour>,
+    pub uvs: Vec<(f64, f64)>,
+    pub triangles: Vec<[usize; 3]>,
+    pub material_ids: Vec<usize>
+}
+
+// An indexed triangle mesh: the vertex buffers are shared by all triangles, which only store
+// indices into them. The triangles are kept in their own flattened BVH, so a mesh with millions of
+// triangles is still a single object in a scene.
+pub struct TriangleMesh {
+    data: MeshData,
+    materials: Vec<Arc<dyn Material>>,
+    nodes: Vec<MeshNode>,
+    bbox: Aabb,
+    emitters: Vec<usize>, // triangles with an emissive material, the only ones sampled as a light
+    emitter_areas: Vec<f64> // running total of the emitters' areas, for picking one by area
+}
+
+// A node of the mesh BVH. Leaves cover `count` triangles starting at `start`, interior nodes have
+// their left child right after them and their right child at `right`.
+struct MeshNode {
+    bbox: Aabb,
+    start: usize,
+    count: usize,
+    right: usize
+}
+
+impl TriangleMesh {
+    pub fn new(mut data: MeshData, materials: Vec<Arc<dyn Material>>) -> Result<TriangleMesh, MeshError> {
+        validate(&data, materials.len())?;
+
+        let mut nodes = vec![];
+        if !data.triangles.is_empty() {
+            let mut order: Vec<usize> = (0..data.triangles.len()).collect();
+            build(&data, &mut order, 0, &mut nodes);
+
+            // store the triangles in tree order so every leaf covers a contiguous run
+            data.triangles = order.iter().map(|&index| data.triangles[index]).collect();
+            if !data.material_ids.is_empty() {
+                data.material_ids = order.iter().map(|&index| data.material_ids[index]).collect();
+            }
+        }
+
+        let bbox = nodes.first().map_or(Aabb::EMPTY, |node: &MeshNode| node.bbox);
+
+        let mut mesh = TriangleMesh { data, materials, nodes, bbox, emitters: vec![], emitter_areas: vec![] };
+
+        let mut total_area = 0.0;
+        for triangle in 0..mesh.triangle_count() {
+            if mesh.material(triangle).is_emissive() {
+                let [v0, v1, v2] = mesh.vertices(triangle);
+                total_area += 0.5 * (v1 - v0).cross(v2 - v0).length();
+
+                mesh.emitters.push(triangle);
+                mesh.emitter_areas.push(total_area);
+            }
+        }
+
+        Ok(mesh)
+    }
+
+    // whether any of the triangles give off light, making the mesh worth sampling as a light
+    pub fn emits_light(&self) -> bool {
+        !self.emitters.is_empty()
+    }
+
+    fn emitter_area(&self) -> f64 {
+        self.emitter_areas.last().copied().unwrap_or(0.0)
+    }
+
+    fn material(&self, triangle: usize) -> &Arc<dyn Material> {
+        match self.data.material_ids.get(triangle) {
+            Some(&id) => &self.materials[id],
+            None => &self.materials[0]
+        }
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.data.triangles.len()
+    }
+
+    fn vertices(&self, triangle: usize) -> [Point3; 3] {
+        let [a, b, c] = self.data.triangles[triangle];
+        [self.data.positions[a], self.data.positions[b], self.data.positions[c]]
+    }
+
+    fn triangle_hit(&self, triangle: usize, ray: &Ray, ray_t: Interval) -> Option<(f64, (f64, f64))> {
+        triangle::intersect(ray, &self.vertices(triangle), ray_t)
+    }
+
+    fn record(&self, triangle: usize, ray: &Ray, t: f64, barycentric: (f64, f64)) -> HitRecord {
+        let [a, b, c] = self.data.triangles[triangle];
+        let [v0, v1, v2] = self.vertices(triangle);
+        let (u, v) = barycentric;
+        let w = 1.0 - u - v;
+
+        let point = v0 * w + v1 * u + v2 * v;
+        let geometric_normal = (v1 - v0).cross(v2 - v0).unit();
+        let material = Arc::clone(self.material(triangle));
+
+        let mut record = HitRecord::new(point, geometric_normal, t, material);
+        record.barycentric = barycentric;
+        record.set_face_normal(ray, geometric_normal);
+
+        // smooth shading: interpolate the vertex normals, keeping them on the same side as the
+        // geometric normal so front_face stays consistent
+        let (dpdu, dpdv, mut dndu, mut dndv) = self.derivatives(triangle);
+        if !self.data.normals.is_empty() {
+            let normals = &self.data.normals;
+            let mut shading_normal = (normals[a] * w + normals[b] * u + normals[c] * v).unit();
+            if shading_normal.dot(geometric_normal) < 0.0 {
+                shading_normal = -shading_normal;
+                (dndu, dndv) = (-dndu, -dndv);
+            }
+
+            if !shading_normal.x.is_nan() {
+                record.normal = if record.front_face { shading_normal } else { -shading_normal };
+            }
+        }
+
+        if !self.data.colours.is_empty() {
+            let colours = &self.data.colours;
+            record.vertex_colour = Some(colours[a] * w + colours[b] * u + colours[c] * v);
+        }
+
+        if !self.data.uvs.is_empty() {
+            let uvs = &self.data.uvs;
+            record.u = uvs[a].0 * w + uvs[b].0 * u + uvs[c].0 * v;
+            record.v = uvs[a].1 * w + uvs[b].1 * u + uvs[c].1 * v;
+        }
+
+        record.set_derivatives(dpdu, dpdv, dndu, dndv);
+
+        record
+    }
+
+    // How the point and the (smooth) normal change across a triangle. Texture coordinates give
+    // the parameterisation when there are any and they aren't degenerate; otherwise it's the
+    // barycentric weights of the second and third vertices.
+    fn derivatives(&self, triangle: usize) -> (Vector3, Vector3, Vector3, Vector3) {
+        let [a, b, c] = self.data.triangles[triangle];
+        let [v0, v1, v2] = self.vertices(triangle);
+        let normals = &self.data.normals;
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        let (n0, n1, n2) = if normals.is_empty() { (zero, zero, zero) } else { (normals[a], normals[b], normals[c]) };
+
+        if !self.data.uvs.is_empty() {
+            let uvs = &self.data.uvs;
+            let duv02 = (uvs[a].0 - uvs[c].0, uvs[a].1 - uvs[c].1);
+            let duv12 = (uvs[b].0 - uvs[c].0, uvs[b].1 - uvs[c].1);
+            let determinant = duv02.0 * duv12.1 - duv02.1 * duv12.0;
+
+            if determinant.abs() > 1e-12 {
+                // solves [d02 d12] = [d/du d/dv] [duv02 duv12] for d/du and d/dv
+                let solve = |d02: Vector3, d12: Vector3| {
+                    ((d02 * duv12.1 - d12 * duv02.1) / determinant, (d12 * duv02.0 - d02 * duv12.0) / determinant)
+                };
+                let (dpdu, dpdv) = solve(v0 - v2, v1 - v2);
+                let (dndu, dndv) = solve(n0 - n2, n1 - n2);
+
+                return (dpdu, dpdv, dndu, dndv);
+            }
+        }
+
+        (v1 - v0, v2 - v0, n1 - n0, n2 - n0)
+    }
+
+    // Visits every leaf whose box the ray passes through until visit returns false. The closest
+    // hit search shrinks ray_t as it goes, so visit gets the current interval each time.
+    fn traverse(&self, ray: &Ray, ray_t: &mut Interval, mut visit: impl FnMut(usize, &mut Interval) -> bool) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bbox.hit(ray, *ray_t) {
+                continue;
+            }
+
+            if node.count > 0 {
+                for triangle in node.start..node.start + node.count {
+                    if !visit(triangle, ray_t) {
+                        return;
+                    }
+                }
+            } else {
+                stack.push(node.right);
+                stack.push(index + 1);
+            }
+        }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut closest = None;
+        let mut search = ray_t;
+
+        self.traverse(ray, &mut search, |triangle, search| {
+            if let Some((t, barycentric)) = self.triangle_hit(triangle, ray, *search) {
+                search.max = t;
+                closest = Some((triangle, t, barycentric));
+            }
+            true
+        });
+
+        closest.map(|(triangle, t, barycentric)| self.record(triangle, ray, t, barycentric))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn any_hit(&self, ray: &Ray, ray_t: Interval) -> bool {
+        let mut found = false;
+        let mut search = ray_t;
+
+        self.traverse(ray, &mut search, |triangle, search| {
+            found = self.triangle_hit(triangle, ray, *search).is_some();
+            !found
+        });
+
+        found
+    }
+
+    fn collect_hits(&self, ray: &Ray, ray_t: Interval, hits: &mut Vec<HitRecord>) {
+        let mut search = ray_t;
+
+        self.traverse(ray, &mut search, |triangle, search| {
+            if let Some((t, barycentric)) = self.triangle_hit(triangle, ray, *search) {
+                hits.push(self.record(triangle, ray, t, barycentric));
+            }
+            true
+        });
+    }
+
+    // Only the emissive triangles are sampled, uniformly over their combined area. A direction can
+    // pass through several of them, and every one is a way random could have produced it.
+    fn pdf_value(&self, origin: Point3, direction: Vector3) -> f64 {
+        if self.emitters.is_empty() {
+            return 0.0;
+        }
+
+        let ray = Ray::new(origin, direction);
+        let area = self.emitter_area();
+        let mut pdf = 0.0;
+        let mut search = Interval::new(0.001, f64::INFINITY);
+
+        self.traverse(&ray, &mut search, |triangle, search| {
+            if self.material(triangle).is_emissive() {
+                if let Some((t, _)) = self.triangle_hit(triangle, &ray, *search) {
+                    let [v0, v1, v2] = self.vertices(triangle);
+                    pdf += triangle::area_pdf(&ray, t, (v1 - v0).cross(v2 - v0), area);
+                }
+            }
+            true
+        });
+
+        pdf
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut Sampler) -> Vector3 {
+        if self.emitters.is_empty() {
+            return Vector3::new(1.0, 0.0, 0.0);
+        }
+
+        let target = sampler.random() * self.emitter_area();
+        let index = self.emitter_areas.partition_point(|&area| area <= target).min(self.emitters.len() - 1);
+
+        triangle::random_point(&self.vertices(self.emitters[index]), sampler) - origin
+    }
+}
+
+fn triangle_bounds(data: &MeshData, triangle: usize) -> Aabb {
+    let [a, b, c] = data.triangles[triangle];
+    let positions = &data.positions;
+
+    Aabb::union(Aabb::from_points(positions[a], positions[b]), Aabb::from_points(positions[b], positions[c]))
+}
+
+// Builds the node for order[start..] (up to the end of the slice) and its children, splitting at
+// the median triangle centre along the longest axis
+fn build(data: &MeshData, order: &mut [usize], start: usize, nodes: &mut Vec<MeshNode>) -> usize {
+    let bbox = order.iter().fold(Aabb::EMPTY, |bbox, &triangle| Aabb::union(bbox, triangle_bounds(data, triangle)));
+    let index = nodes.len();
+
+    if order.len() <= MAX_LEAF_TRIANGLES {
+        nodes.push(MeshNode { bbox, start, count: order.len(), right: 0 });
+        return index;
+    }
+
+    let centroids = order.iter().fold(Aabb::EMPTY, |bounds, &triangle| {
+        let centre = triangle_bounds(data, triangle).centroid();
+        Aabb::union(bounds, Aabb::from_points(centre, centre))
+    });
+    let axis = centroids.longest_axis();
+
+    let middle = order.len() / 2;
+    order.select_nth_unstable_by(middle, |&a, &b| {
+        triangle_bounds(data, a).centroid()[axis].total_cmp(&triangle_bounds(data, b).centroid()[axis])
+    });
+
+    nodes.push(MeshNode { bbox, start, count: 0, right: 0 });
+
+    let (left, right) = order.split_at_mut(middle);
+    build(data, left, start, nodes);
+    let right_index = build(data, right, start + middle, nodes);
+    nodes[index].right = right_index;
+
+    index
+}
+
+fn validate(data: &MeshData, material_count: usize) -> Result<(), MeshError> {
+    let vertex_count = data.positions.len();
+
+    if !data.normals.is_empty() && data.normals.len() != vertex_count {
+        return Err(MeshError::AttributeCount { attribute: "normals", expected: vertex_count, found: data.normals.len() });
+    }
+
+    if !data.colours.is_empty() && data.colours.len() != vertex_count {
+        return Err(MeshError::AttributeCount { attribute: "colours", expected: vertex_count, found: data.colours.len() });
+    }
+
+    if !data.uvs.is_empty() && data.uvs.len() != vertex_count {
+        return Err(MeshError::AttributeCount { attribute: "uvs", expected: vertex_count, found: data.uvs.len() });
+    }
+
+    if !data.material_ids.is_empty() && data.material_ids.len() != data.triangles.len() {
+        return Err(MeshError::AttributeCount { attribute: "material ids", expected: data.triangles.len(), found: data.material_ids.len() });
+    }
+
+    if material_count == 0 {
+        return Err(MeshError::NoMaterials);
+    }
+
+    for (triangle, indices) in data.triangles.iter().enumerate() {
+        if let Some(&index) = indices.iter().find(|&&index| index >= vertex_count) {
+            return Err(MeshError::VertexIndex { triangle, index, vertex_count });
+        }
+    }
+
+    if let Some((triangle, &id)) = data.material_ids.iter().enumerate().find(|(_, &id)| id >= material_count) {
+        return Err(MeshError::MaterialIndex { triangle, id, material_count });
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MeshError {
+    NoMaterials,
+    AttributeCount { attribute: &'static str, expected: usize, found: usize },
+    VertexIndex { triangle: usize, index: usize, vertex_count: usize },
+    MaterialIndex { triangle: usize, id: usize, material_count: usize }
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::NoMaterials => write!(f, "a mesh needs at least one material"),
+            MeshError::AttributeCount { attribute, expected, found } =>
+                write!(f, "expected {} {} but found {}", expected, attribute, found),
+            MeshError::VertexIndex { triangle, index, vertex_count } =>
+                write!(f, "triangle {} uses vertex {} but the mesh only has {} vertices", triangle, index, vertex_count),
+            MeshError::MaterialIndex { triangle, id, material_count } =>
+                write!(f, "triangle {} uses material {} but the mesh only has {} materials", triangle, id, material_count)
+        }
+    }
+}
+
+impl Error for MeshError {}
@@ -0,0 +1,299 @@
+use crate::loaders::image::Image;
+use crate::vector3::Colour;
+
+// How an image texture averages the texels under a ray's footprint
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFilter {
+    // blends the four nearest texels of the full size image, whatever the footprint
+    Bilinear,
+    // blends the two pyramid levels whose texels are closest to the footprint's size. Cheap, but
+    // the footprint is treated as a square, so surfaces seen at a grazing angle blur.
+    Trilinear,
+    // elliptically weighted average: sums texels under the footprint's actual ellipse, which
+    // stays sharp across the narrow axis of stretched footprints
+    Ewa
+}
+
+// longest an EWA ellipse can be compared to its width. Longer ones are widened, trading a little
+// blur for a bounded number of texels per lookup.
+const MAX_ANISOTROPY: f64 = 8.0;
+
+// falloff of the gaussian EWA weights
+const EWA_ALPHA: f64 = 2.0;
+
+// most texels one EWA lookup may visit. Ellipses that would cover more, which only rounding in
+// degenerate footprints should produce, fall back to a trilinear lookup as wide as the ellipse.
+const MAX_EWA_TEXELS: i64 = 4096;
+
+#[cfg(test)]
+thread_local! {
+    // texels EWA lookups have visited on this thread, so tests can check the cap holds
+    static TEXELS_VISITED: std::cell::Cell<i64> = const { std::cell::Cell::new(0) };
+}
+
+// An image together with successively halved copies of it, down to a single texel. Lookups are
+// in texture space, s across and t down the image, both from 0 to 1; anything outside is clamped
+// to the edge texels.
+pub struct MipMap {
+    levels: Vec<Image>
+}
+
+impl MipMap {
+    pub fn new(image: Image) -> MipMap {
+        let mut levels = vec![image];
+
+        while let Some(last) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+            levels.push(downsample(last));
+        }
+
+        MipMap { levels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    // s and t derivatives of texture space with respect to the x and y pixel directions
+    pub fn filter(&self, filter: TextureFilter, s: f64, t: f64, dst_dx: (f64, f64), dst_dy: (f64, f64)) -> Colour {
+        match filter {
+            TextureFilter::Bilinear => self.bilinear(0, s, t),
+            TextureFilter::Trilinear => {
+                let width = 2.0 * [dst_dx.0, dst_dx.1, dst_dy.0, dst_dy.1].iter().fold(0.0, |widest: f64, d| widest.max(d.abs()));
+                self.trilinear(s, t, width)
+            }
+            TextureFilter::Ewa => self.ewa(s, t, dst_dx, dst_dy)
+        }
+    }
+
+    // bilinear interpolation between the four texel centres around (s, t)
+    pub fn bilinear(&self, level: usize, s: f64, t: f64) -> Colour {
+        let image = &self.levels[level.min(self.levels.len() - 1)];
+
+        let x = s * image.width as f64 - 0.5;
+        let y = t * image.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        texel(image, x0, y0) * ((1.0 - dx) * (1.0 - dy))
+            + texel(image, x0 + 1, y0) * (dx * (1.0 - dy))
+            + texel(image, x0, y0 + 1) * ((1.0 - dx) * dy)
+            + texel(image, x0 + 1, y0 + 1) * (dx * dy)
+    }
+
+    // A square filter width across in texture space: picks the level where a texel is about
+    // that wide, blending with the next level for widths in between
+    fn trilinear(&self, s: f64, t: f64, width: f64) -> Colour {
+        let level = self.level_for(width);
+        if level <= 0.0 {
+            return self.bilinear(0, s, t);
+        }
+
+        let below = level.floor();
+        let blend = level - below;
+        let below = below as usize;
+
+        self.bilinear(below, s, t) * (1.0 - blend) + self.bilinear(below + 1, s, t) * blend
+    }
+
+    // Heckbert's EWA, as laid out in pbrt: the two derivative vectors are the ellipse's axes,
+    // and the level is chosen by its minor axis so the ellipse covers a few texels across
+    fn ewa(&self, s: f64, t: f64, dst_dx: (f64, f64), dst_dy: (f64, f64)) -> Colour {
+        let length = |(ds, dt): (f64, f64)| (ds * ds + dt * dt).sqrt();
+
+        let (major, mut minor) = if length(dst_dx) >= length(dst_dy) { (dst_dx, dst_dy) } else { (dst_dy, dst_dx) };
+        let major_length = length(major);
+        let mut minor_length = length(minor);
+
+        if minor_length * MAX_ANISOTROPY < major_length && minor_length > 0.0 {
+            let scale = major_length / (minor_length * MAX_ANISOTROPY);
+            minor = (minor.0 * scale, minor.1 * scale);
+            minor_length *= scale;
+        }
+
+        if minor_length == 0.0 || !minor_length.is_finite() || !major_length.is_finite() {
+            return self.bilinear(0, s, t);
+        }
+
+        // footprints wider than the whole image just see its average
+        let level = self.level_for(minor_length).max(0.0);
+        let top = self.levels.len() - 1;
+        if level >= top as f64 {
+            return texel(&self.levels[top], 0, 0);
+        }
+
+        let below = level.floor();
+        let blend = level - below;
+        let below = below as usize;
+
+        self.ewa_level(below, s, t, major, minor) * (1.0 - blend) + self.ewa_level(below + 1, s, t, major, minor) * blend
+    }
+
+    fn ewa_level(&self, level: usize, s: f64, t: f64, axis0: (f64, f64), axis1: (f64, f64)) -> Colour {
+        let top = self.levels.len() - 1;
+        if level >= top {
+            return texel(&self.levels[top], 0, 0);
+        }
+
+        let image = &self.levels[level];
+        let (width, height) = (image.width as f64, image.height as f64);
+
+        // into this level's texel coordinates
+        let s = s * width - 0.5;
+        let t = t * height - 0.5;
+        let axis0 = (axis0.0 * width, axis0.1 * height);
+        let axis1 = (axis1.0 * width, axis1.1 * height);
+
+        // implicit ellipse A s^2 + B s t + C t^2 < 1, with at least a texel's width either way
+        let mut a = axis0.1 * axis0.1 + axis1.1 * axis1.1 + 1.0;
+        let mut b = -2.0 * (axis0.0 * axis0.1 + axis1.0 * axis1.1);
+        let mut c = axis0.0 * axis0.0 + axis1.0 * axis1.0 + 1.0;
+        let inverse_f = 1.0 / (a * c - b * b * 0.25);
+        a *= inverse_f;
+        b *= inverse_f;
+        c *= inverse_f;
+
+        // the ellipse's bounding box
+        let det = -b * b + 4.0 * a * c;
+        let inverse_det = 1.0 / det;
+        let s_extent = 2.0 * inverse_det * (det * c).sqrt();
+        let t_extent = 2.0 * inverse_det * (a * det).sqrt();
+        let (s0, s1) = ((s - s_extent).ceil() as i64, (s + s_extent).floor() as i64);
+        let (t0, t1) = ((t - t_extent).ceil() as i64, (t + t_extent).floor() as i64);
+
+        let texels = s1.saturating_sub(s0).saturating_add(1).saturating_mul(t1.saturating_sub(t0).saturating_add(1));
+        if texels > MAX_EWA_TEXELS || !(s_extent.is_finite() && t_extent.is_finite()) {
+            let filter_width = 2.0 * (s_extent / width).max(t_extent / height);
+            return self.trilinear((s + 0.5) / width, (t + 0.5) / height, filter_width);
+        }
+
+        #[cfg(test)]
+        TEXELS_VISITED.with(|visited| visited.set(visited.get() + texels));
+
+        let mut sum = Colour::new(0.0, 0.0, 0.0);
+        let mut weight_sum = 0.0;
+        for it in t0..=t1 {
+            let tt = it as f64 - t;
+            for is in s0..=s1 {
+                let ss = is as f64 - s;
+
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    let weight = (-EWA_ALPHA * r2).exp() - (-EWA_ALPHA).exp();
+                    sum += texel(image, is, it) * weight;
+                    weight_sum += weight;
+                }
+            }
+        }
+
+        if weight_sum <= 0.0 {
+            return self.bilinear(level, (s + 0.5) / width, (t + 0.5) / height);
+        }
+
+        sum / weight_sum
+    }
+
+    // the (fractional) level where a texel is width across, in texture space
+    fn level_for(&self, width: f64) -> f64 {
+        let resolution = self.width().max(self.height()) as f64;
+        let level = (width.max(1e-8) * resolution).log2();
+
+        level.min((self.levels.len() - 1) as f64)
+    }
+}
+
+// the texel at (x, y), clamped to the image's edges
+fn texel(image: &Image, x: i64, y: i64) -> Colour {
+    let x = x.clamp(0, image.width as i64 - 1) as usize;
+    let y = y.clamp(0, image.height as i64 - 1) as usize;
+
+    image.pixel(x, y)
+}
+
+// Halves an image with a box filter. Odd sizes round up, with the last row or column of the
+// smaller image averaging the edge of the larger one with itself.
+fn downsample(image: &Image) -> Image {
+    let width = image.width.div_ceil(2);
+    let height = image.height.div_ceil(2);
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let (x0, y0) = (2 * x as i64, 2 * y as i64);
+            let sum = texel(image, x0, y0) + texel(image, x0 + 1, y0) + texel(image, x0, y0 + 1) + texel(image, x0 + 1, y0 + 1);
+            pixels.push(sum * 0.25);
+        }
+    }
+
+    Image { width, height, pixels }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loaders::image::Image;
+    use crate::mipmap::{MipMap, TextureFilter, MAX_EWA_TEXELS, TEXELS_VISITED};
+    use crate::vector3::Colour;
+
+    // a 256 x 256 checkerboard of single black and white texels, which averages to grey
+    fn checkerboard() -> MipMap {
+        let size = 256;
+        let pixels = (0..size * size)
+            .map(|index| if (index % size + index / size) % 2 == 0 { Colour::new(1.0, 1.0, 1.0) } else { Colour::new(0.0, 0.0, 0.0) })
+            .collect();
+
+        MipMap::new(Image { width: size, height: size, pixels })
+    }
+
+    // the result of a lookup and how many texels the EWA loop visited for it
+    fn counting_texels(lookup: impl FnOnce() -> Colour) -> (Colour, i64) {
+        TEXELS_VISITED.with(|visited| visited.set(0));
+        let colour = lookup();
+
+        (colour, TEXELS_VISITED.with(|visited| visited.get()))
+    }
+
+    #[test]
+    fn huge_ewa_footprint_is_quick_and_averages_the_image() {
+        let mipmap = checkerboard();
+
+        for width in [1000.0, 3000.0, 1e8] {
+            let (colour, texels) = counting_texels(|| mipmap.filter(TextureFilter::Ewa, 0.3, 0.7, (width, 0.0), (0.0, width * 0.5)));
+
+            // the single texel at the top of the pyramid already holds the average
+            assert_eq!(texels, 0, "lookup {} wide", width);
+            assert!((colour.x - 0.5).abs() < 1e-9, "expected the average grey, got {:?}", colour);
+        }
+    }
+
+    #[test]
+    fn stretched_ewa_footprint_stays_bounded() {
+        let mipmap = checkerboard();
+
+        // very long and thin, past the anisotropy limit
+        let (colour, texels) = counting_texels(|| mipmap.filter(TextureFilter::Ewa, 0.5, 0.5, (0.5, 0.0), (0.0, 1e-6)));
+
+        // two levels are blended, each visiting at most the cap
+        assert!(texels > 0 && texels <= 2 * MAX_EWA_TEXELS, "visited {} texels", texels);
+        assert!((colour.x - 0.5).abs() < 0.05, "expected about grey, got {:?}", colour);
+    }
+
+    #[test]
+    fn ewa_level_caps_texels_visited() {
+        let mipmap = checkerboard();
+
+        // an ellipse far too big for the full size level, as rounding could ask for, falls back
+        // to a trilinear lookup without visiting any
+        let (colour, texels) = counting_texels(|| mipmap.ewa_level(0, 0.5, 0.5, (50.0, 0.0), (0.0, 50.0)));
+
+        assert_eq!(texels, 0);
+        assert!((colour.x - 0.5).abs() < 1e-9, "expected the average grey, got {:?}", colour);
+
+        // while one just under the cap is summed in full
+        let (_, texels) = counting_texels(|| mipmap.ewa_level(0, 0.5, 0.5, (0.1, 0.0), (0.0, 0.1)));
+        assert!(texels > MAX_EWA_TEXELS / 4 && texels <= MAX_EWA_TEXELS, "visited {} texels", texels);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::vector3::Vector3;
+
+// Orthonormal basis around a direction w, for turning directions sampled around the z axis into
+// directions around w
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vector3,
+    pub v: Vector3,
+    pub w: Vector3
+}
+
+impl Onb {
+    pub fn new(w: Vector3) -> Onb {
+        let w = w.unit();
+
+        // any vector not parallel to w will do to start the cross products
+        let a = if w.x.abs() > 0.9 {
+            Vector3::new(0.0, 1.0, 0.0)
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+
+        let v = w.cross(a).unit();
+        let u = w.cross(v);
+
+        Onb { u, v, w }
+    }
+
+    // from basis coordinates to world space
+    pub fn transform(&self, local: Vector3) -> Vector3 {
+        self.u * local.x + self.v * local.y + self.w * local.z
+    }
+}
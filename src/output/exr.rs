@@ -0,0 +1,309 @@
+use std::io::Write;
+use half::f16;
+use crate::framebuffer::Framebuffer;
+use crate::output::{ImageError, ImageWriter};
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const VERSION: [u8; 4] = [2, 0, 0, 0]; // version 2, single-part scanline file
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExrPrecision {
+    Half,
+    Float
+}
+
+impl ExrPrecision {
+    // pixel type code used in the channel list
+    fn pixel_type(self) -> i32 {
+        match self {
+            ExrPrecision::Half => 1,
+            ExrPrecision::Float => 2
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ExrPrecision::Half => 2,
+            ExrPrecision::Float => 4
+        }
+    }
+}
+
+// Uncompressed scanline OpenEXR holding the unclamped linear radiance as R, G and B, plus every
+// auxiliary layer of the framebuffer as "<layer>.<channel>" (e.g. normal.X, depth.Z)
+pub struct ExrWriter {
+    precision: ExrPrecision
+}
+
+impl ExrWriter {
+    pub fn new(precision: ExrPrecision) -> ExrWriter {
+        ExrWriter { precision }
+    }
+}
+
+// A named channel and the values of all its pixels, row by row
+struct Channel {
+    name: String,
+    values: Vec<f32>
+}
+
+impl ImageWriter for ExrWriter {
+    fn write(&self, framebuffer: &Framebuffer, out: &mut dyn Write) -> Result<(), ImageError> {
+        let width = framebuffer.width() as usize;
+        let height = framebuffer.height() as usize;
+        let channels = collect_channels(framebuffer);
+
+        let header = self.header(framebuffer, &channels);
+
+        // the offset table holds the file position of every scanline chunk. Each chunk is the
+        // line number and data size followed by one row of every channel.
+        let line_size = width * channels.len() * self.precision.size();
+        let chunk_size = 4 + 4 + line_size;
+        let first_chunk = MAGIC.len() + VERSION.len() + header.len() + height * 8;
+
+        out.write_all(&MAGIC)?;
+        out.write_all(&VERSION)?;
+        out.write_all(&header)?;
+        for y in 0..height {
+            out.write_all(&((first_chunk + y * chunk_size) as u64).to_le_bytes())?;
+        }
+
+        let mut chunk = Vec::with_capacity(chunk_size);
+        for y in 0..height {
+            chunk.clear();
+            chunk.extend_from_slice(&(y as i32).to_le_bytes());
+            chunk.extend_from_slice(&(line_size as i32).to_le_bytes());
+
+            for channel in &channels {
+                for value in &channel.values[y * width..(y + 1) * width] {
+                    match self.precision {
+                        ExrPrecision::Half => chunk.extend_from_slice(&f16::from_f32(*value).to_le_bytes()),
+                        ExrPrecision::Float => chunk.extend_from_slice(&value.to_le_bytes())
+                    }
+                }
+            }
+
+            out.write_all(&chunk)?;
+        }
+
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+impl ExrWriter {
+    fn header(&self, framebuffer: &Framebuffer, channels: &[Channel]) -> Vec<u8> {
+        let mut header = vec![];
+
+        let mut channel_list = vec![];
+        for channel in channels {
+            channel_list.extend_from_slice(channel.name.as_bytes());
+            channel_list.push(0);
+            channel_list.extend_from_slice(&self.precision.pixel_type().to_le_bytes());
+            channel_list.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved bytes
+            channel_list.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+            channel_list.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+        }
+        channel_list.push(0);
+        attribute(&mut header, "channels", "chlist", &channel_list);
+
+        attribute(&mut header, "compression", "compression", &[0]); // no compression
+
+        let mut window = vec![];
+        for value in [0, 0, framebuffer.width() as i32 - 1, framebuffer.height() as i32 - 1] {
+            window.extend_from_slice(&value.to_le_bytes());
+        }
+        attribute(&mut header, "dataWindow", "box2i", &window);
+        attribute(&mut header, "displayWindow", "box2i", &window);
+
+        attribute(&mut header, "lineOrder", "lineOrder", &[0]); // increasing y
+        attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+        attribute(&mut header, "screenWindowCenter", "v2f", &[0u8; 8]);
+        attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+
+        // end of header
+        header.push(0);
+
+        header
+    }
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(attribute_type.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// EXR requires the channels to be listed, and stored within each scanline, in alphabetical order
+fn collect_channels(framebuffer: &Framebuffer) -> Vec<Channel> {
+    let pixels = framebuffer.pixels();
+    let mut channels = vec![
+        Channel { name: "R".to_string(), values: pixels.iter().map(|pixel| pixel.x as f32).collect() },
+        Channel { name: "G".to_string(), values: pixels.iter().map(|pixel| pixel.y as f32).collect() },
+        Channel { name: "B".to_string(), values: pixels.iter().map(|pixel| pixel.z as f32).collect() }
+    ];
+
+    for layer in framebuffer.layers() {
+        for (index, channel) in layer.channels().iter().enumerate() {
+            channels.push(Channel {
+                name: format!("{}.{}", layer.name(), channel),
+                values: layer.channel(index).collect()
+            });
+        }
+    }
+
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    channels
+}
+
+#[cfg(test)]
+mod tests {
+    use half::f16;
+    use crate::framebuffer::Framebuffer;
+    use crate::output::{ExrPrecision, ExrWriter, ImageWriter};
+    use crate::output::test_support::framebuffer;
+
+    // the fixture plus a depth layer and a normal layer, added out of order
+    fn layered_framebuffer() -> Framebuffer {
+        let mut framebuffer = framebuffer();
+        let normal = framebuffer.add_layer("normal", &["X", "Y", "Z"]);
+        normal.set(0, 0, &[0.0, 1.0, 0.0]);
+        normal.set(1, 1, &[-0.6, 0.0, 0.8]);
+        framebuffer.add_layer("depth", &["Z"]).set(1, 0, &[12.5]);
+        framebuffer
+    }
+
+    // reads a null terminated string, moving the position past it
+    fn string(bytes: &[u8], position: &mut usize) -> String {
+        let length = bytes[*position..].iter().position(|&byte| byte == 0).unwrap();
+        let string = String::from_utf8(bytes[*position..*position + length].to_vec()).unwrap();
+        *position += length + 1;
+        string
+    }
+
+    fn int(bytes: &[u8], position: usize) -> i32 {
+        i32::from_le_bytes(bytes[position..position + 4].try_into().unwrap())
+    }
+
+    // The header's attributes as (name, type, value), and the position just past the header
+    fn read_header(bytes: &[u8]) -> (Vec<(String, String, Vec<u8>)>, usize) {
+        let mut attributes = vec![];
+        let mut position = 8;
+        while bytes[position] != 0 {
+            let name = string(bytes, &mut position);
+            let attribute_type = string(bytes, &mut position);
+            let size = int(bytes, position) as usize;
+            attributes.push((name, attribute_type, bytes[position + 4..position + 4 + size].to_vec()));
+            position += 4 + size;
+        }
+
+        (attributes, position + 1)
+    }
+
+    // the name and pixel type of every entry of a channel list
+    fn channel_list(value: &[u8]) -> Vec<(String, i32)> {
+        let mut channels = vec![];
+        let mut position = 0;
+        while value[position] != 0 {
+            let name = string(value, &mut position);
+            channels.push((name, int(value, position)));
+            // pixel type, pLinear and reserved, and the x and y sampling
+            position += 16;
+        }
+        channels
+    }
+
+    fn write(precision: ExrPrecision) -> Vec<u8> {
+        let mut bytes = vec![];
+        ExrWriter::new(precision).write(&layered_framebuffer(), &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn header_lists_channels_in_order() {
+        let bytes = write(ExrPrecision::Half);
+
+        assert_eq!(bytes[..4], [0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(bytes[4..8], [2, 0, 0, 0]);
+
+        let (attributes, _) = read_header(&bytes);
+        let names: Vec<(&str, &str)> = attributes.iter().map(|(name, kind, _)| (name.as_str(), kind.as_str())).collect();
+        assert_eq!(names, vec![
+            ("channels", "chlist"),
+            ("compression", "compression"),
+            ("dataWindow", "box2i"),
+            ("displayWindow", "box2i"),
+            ("lineOrder", "lineOrder"),
+            ("pixelAspectRatio", "float"),
+            ("screenWindowCenter", "v2f"),
+            ("screenWindowWidth", "float")
+        ]);
+
+        let channels = channel_list(&attributes[0].2);
+        let expected = ["B", "G", "R", "depth.Z", "normal.X", "normal.Y", "normal.Z"];
+        assert_eq!(channels, expected.iter().map(|name| (name.to_string(), 1)).collect::<Vec<_>>());
+
+        // no compression, and both windows are the whole 2x2 image
+        assert_eq!(attributes[1].2, [0]);
+        let window: Vec<i32> = (0..4).map(|index| int(&attributes[2].2, index * 4)).collect();
+        assert_eq!(window, [0, 0, 1, 1]);
+        assert_eq!(attributes[3].2, attributes[2].2);
+
+        let float = write(ExrPrecision::Float);
+        assert!(channel_list(&read_header(&float).0[0].2).iter().all(|(_, pixel_type)| *pixel_type == 2));
+    }
+
+    #[test]
+    fn scanlines_round_trip_through_the_offset_table() {
+        let framebuffer = layered_framebuffer();
+
+        for (precision, size) in [(ExrPrecision::Half, 2), (ExrPrecision::Float, 4)] {
+            let bytes = write(precision);
+            let (_, end) = read_header(&bytes);
+            let line_size = 2 * 7 * size;
+
+            for y in 0..2 {
+                let offset = u64::from_le_bytes(bytes[end + y * 8..end + y * 8 + 8].try_into().unwrap()) as usize;
+                assert_eq!(offset, end + 2 * 8 + y * (8 + line_size));
+                assert_eq!(int(&bytes, offset), y as i32);
+                assert_eq!(int(&bytes, offset + 4), line_size as i32);
+
+                let values: Vec<f32> = bytes[offset + 8..offset + 8 + line_size].chunks_exact(size)
+                    .map(|value| match precision {
+                        ExrPrecision::Half => f16::from_le_bytes(value.try_into().unwrap()).to_f32(),
+                        ExrPrecision::Float => f32::from_le_bytes(value.try_into().unwrap())
+                    })
+                    .collect();
+
+                // each channel's row in turn, in the same order as the channel list
+                let pixel = |x: u32| framebuffer.get(x, y as u32);
+                let normal = |x: u32| framebuffer.layer("normal").unwrap().get(x, y as u32);
+                let depth = |x: u32| framebuffer.layer("depth").unwrap().get(x, y as u32)[0];
+                let expected: Vec<f32> = [
+                    [pixel(0).z as f32, pixel(1).z as f32],
+                    [pixel(0).y as f32, pixel(1).y as f32],
+                    [pixel(0).x as f32, pixel(1).x as f32],
+                    [depth(0), depth(1)],
+                    [normal(0)[0], normal(1)[0]],
+                    [normal(0)[1], normal(1)[1]],
+                    [normal(0)[2], normal(1)[2]]
+                ].concat();
+
+                for (value, expected) in values.iter().zip(&expected) {
+                    let expected = match precision {
+                        ExrPrecision::Half => f16::from_f32(*expected).to_f32(),
+                        ExrPrecision::Float => *expected
+                    };
+                    assert_eq!(value, &expected);
+                }
+            }
+
+            assert_eq!(bytes.len(), end + 2 * 8 + 2 * (8 + line_size));
+        }
+    }
+}
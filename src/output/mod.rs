@@ -0,0 +1,140 @@
+mod exr;
+mod pfm;
+mod png;
+mod ppm;
+pub mod tonemap;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use crate::framebuffer::Framebuffer;
+
+pub use exr::{ExrPrecision, ExrWriter};
+pub use pfm::PfmWriter;
+pub use png::{PngBitDepth, PngWriter};
+pub use ppm::PpmWriter;
+pub use tonemap::ToneMapping;
+
+// Encodes a rendered framebuffer into some image format. Writers only see a byte sink, so the
+// caller decides whether the image ends up in a file, on stdout or in memory.
+pub trait ImageWriter {
+    fn write(&self, framebuffer: &Framebuffer, out: &mut dyn Write) -> Result<(), ImageError>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    PpmAscii,
+    PpmBinary,
+    Png8,
+    Png16,
+    Pfm,
+    ExrHalf,
+    ExrFloat
+}
+
+impl ImageFormat {
+    // Picks a format from a file extension: .ppm is written as binary P6, .png as 8-bit PNG and
+    // .exr with half floats
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::PpmBinary),
+            "png" => Some(ImageFormat::Png8),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::ExrHalf),
+            _ => None
+        }
+    }
+
+    // Only EXR has anywhere to put the normal and depth layers
+    pub fn stores_auxiliary_layers(self) -> bool {
+        matches!(self, ImageFormat::ExrHalf | ImageFormat::ExrFloat)
+    }
+
+    // The tone mapping is only used by the 8 and 16 bit formats, float formats store raw radiance
+    pub fn writer(self, tone_mapping: ToneMapping) -> Box<dyn ImageWriter> {
+        match self {
+            ImageFormat::PpmAscii => Box::new(PpmWriter::ascii(tone_mapping)),
+            ImageFormat::PpmBinary => Box::new(PpmWriter::binary(tone_mapping)),
+            ImageFormat::Png8 => Box::new(PngWriter::new(PngBitDepth::Eight, tone_mapping)),
+            ImageFormat::Png16 => Box::new(PngWriter::new(PngBitDepth::Sixteen, tone_mapping)),
+            ImageFormat::Pfm => Box::new(PfmWriter),
+            ImageFormat::ExrHalf => Box::new(ExrWriter::new(ExrPrecision::Half)),
+            ImageFormat::ExrFloat => Box::new(ExrWriter::new(ExrPrecision::Float))
+        }
+    }
+}
+
+// Explicit format names, for when the file extension isn't enough (or there is no file)
+impl FromStr for ImageFormat {
+    type Err = ImageError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "p3" | "ppm-ascii" => Ok(ImageFormat::PpmAscii),
+            "p6" | "ppm" => Ok(ImageFormat::PpmBinary),
+            "png" | "png8" => Ok(ImageFormat::Png8),
+            "png16" => Ok(ImageFormat::Png16),
+            "pfm" => Ok(ImageFormat::Pfm),
+            "exr" | "exr16" => Ok(ImageFormat::ExrHalf),
+            "exr32" => Ok(ImageFormat::ExrFloat),
+            _ => Err(ImageError::UnknownFormat(name.to_string()))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    Encoding(String),
+    UnknownFormat(String),
+    UnknownToneMapOperator(String)
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(error) => write!(f, "failed to write image: {}", error),
+            ImageError::Encoding(message) => write!(f, "failed to encode image: {}", message),
+            ImageError::UnknownFormat(name) => write!(f, "unknown image format '{}'", name),
+            ImageError::UnknownToneMapOperator(name) => write!(f, "unknown tone map operator '{}'", name)
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImageError::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(error: io::Error) -> Self {
+        ImageError::Io(error)
+    }
+}
+
+// Shared by the writers' tests
+#[cfg(test)]
+pub(crate) mod test_support {
+    use crate::framebuffer::Framebuffer;
+    use crate::vector3::Colour;
+
+    // Two rows so the row order shows, with values outside [0, 1] and too small or too big for
+    // 8 bits
+    pub fn framebuffer() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(2, 2);
+        framebuffer.set(0, 0, Colour::new(0.0, 0.5, 1.0));
+        framebuffer.set(1, 0, Colour::new(2.0, 0.18, 0.001));
+        framebuffer.set(0, 1, Colour::new(1.5, -2.0, 100.0));
+        framebuffer.set(1, 1, Colour::new(0.25, 0.0, 3.0e-5));
+        framebuffer
+    }
+}
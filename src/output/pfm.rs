@@ -0,0 +1,53 @@
+use std::io::Write;
+use crate::framebuffer::Framebuffer;
+use crate::output::{ImageError, ImageWriter};
+
+// Portable float map: 32-bit float RGB with no gamma correction or clamping, so the full range
+// of the rendered radiance survives
+pub struct PfmWriter;
+
+impl ImageWriter for PfmWriter {
+    fn write(&self, framebuffer: &Framebuffer, out: &mut dyn Write) -> Result<(), ImageError> {
+        // a negative scale marks the data as little-endian
+        writeln!(out, "PF")?;
+        writeln!(out, "{} {}", framebuffer.width(), framebuffer.height())?;
+        writeln!(out, "-1.0")?;
+
+        // PFM scanlines run from the bottom of the image to the top
+        let row_length = framebuffer.width() as usize;
+        let mut row = Vec::with_capacity(row_length * 12);
+        for pixels in framebuffer.pixels().chunks(row_length).rev() {
+            row.clear();
+            for pixel in pixels {
+                row.extend_from_slice(&(pixel.x as f32).to_le_bytes());
+                row.extend_from_slice(&(pixel.y as f32).to_le_bytes());
+                row.extend_from_slice(&(pixel.z as f32).to_le_bytes());
+            }
+            out.write_all(&row)?;
+        }
+
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{ImageWriter, PfmWriter};
+    use crate::output::test_support::framebuffer;
+
+    #[test]
+    fn writes_little_endian_rows_bottom_up() {
+        let mut bytes = vec![];
+        PfmWriter.write(&framebuffer(), &mut bytes).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+
+        let floats: Vec<f32> = bytes[header.len()..].chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(floats, vec![1.5, -2.0, 100.0, 0.25, 0.0, 3.0e-5, 0.0, 0.5, 1.0, 2.0, 0.18, 0.001]);
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::Write;
+use crate::framebuffer::Framebuffer;
+use crate::output::{ImageError, ImageWriter, ToneMapping};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PngBitDepth {
+    Eight,
+    Sixteen
+}
+
+// sRGB-encoded PNG with 8 or 16 bits per channel
+pub struct PngWriter {
+    bit_depth: PngBitDepth,
+    tone_mapping: ToneMapping
+}
+
+impl PngWriter {
+    pub fn new(bit_depth: PngBitDepth, tone_mapping: ToneMapping) -> PngWriter {
+        PngWriter { bit_depth, tone_mapping }
+    }
+}
+
+impl ImageWriter for PngWriter {
+    fn write(&self, framebuffer: &Framebuffer, out: &mut dyn Write) -> Result<(), ImageError> {
+        let mut encoder = png::Encoder::new(&mut *out, framebuffer.width(), framebuffer.height());
+        encoder.set_color(png::ColorType::Rgb);
+
+        let data = match self.bit_depth {
+            PngBitDepth::Eight => {
+                encoder.set_depth(png::BitDepth::Eight);
+                framebuffer.pixels().iter().flat_map(|pixel| self.tone_mapping.encode_rgb8(*pixel)).collect::<Vec<u8>>()
+            }
+            PngBitDepth::Sixteen => {
+                // 16 bit samples are stored big-endian
+                encoder.set_depth(png::BitDepth::Sixteen);
+                framebuffer.pixels().iter()
+                    .flat_map(|pixel| self.tone_mapping.encode_rgb16(*pixel))
+                    .flat_map(|channel| channel.to_be_bytes())
+                    .collect::<Vec<u8>>()
+            }
+        };
+
+        let mut writer = encoder.write_header().map_err(encoding_error)?;
+        writer.write_image_data(&data).map_err(encoding_error)?;
+        writer.finish().map_err(encoding_error)?;
+
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+fn encoding_error(error: png::EncodingError) -> ImageError {
+    match error {
+        png::EncodingError::IoError(error) => ImageError::Io(error),
+        error => ImageError::Encoding(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{ImageWriter, PngBitDepth, PngWriter, ToneMapping};
+    use crate::output::test_support::framebuffer;
+
+    // encodes and decodes again, giving the bit depth and raw samples
+    fn round_trip(bit_depth: PngBitDepth) -> (png::BitDepth, Vec<u8>) {
+        let mut bytes = vec![];
+        PngWriter::new(bit_depth, ToneMapping::default()).write(&framebuffer(), &mut bytes).unwrap();
+
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+
+        assert_eq!((info.width, info.height, info.color_type), (2, 2, png::ColorType::Rgb));
+        data.truncate(info.buffer_size());
+
+        (info.bit_depth, data)
+    }
+
+    #[test]
+    fn eight_bit_round_trip() {
+        let (bit_depth, data) = round_trip(PngBitDepth::Eight);
+
+        assert_eq!(bit_depth, png::BitDepth::Eight);
+        assert_eq!(data, vec![0, 188, 255, 255, 118, 3, 255, 0, 255, 137, 0, 0]);
+    }
+
+    #[test]
+    fn sixteen_bit_round_trip_is_big_endian() {
+        let (bit_depth, data) = round_trip(PngBitDepth::Sixteen);
+        let samples: Vec<u16> = data.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+
+        assert_eq!(bit_depth, png::BitDepth::Sixteen);
+        assert_eq!(samples, vec![0, 48192, 65535, 65535, 30235, 847, 65535, 0, 65535, 35199, 0, 25]);
+    }
+}
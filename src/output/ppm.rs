@@ -0,0 +1,59 @@
+use std::io::Write;
+use crate::framebuffer::Framebuffer;
+use crate::output::{ImageError, ImageWriter, ToneMapping};
+
+// 8-bit PPM, either plain text (P3, one pixel per line) or the much smaller binary form (P6)
+pub struct PpmWriter {
+    binary: bool,
+    tone_mapping: ToneMapping
+}
+
+impl PpmWriter {
+    pub fn ascii(tone_mapping: ToneMapping) -> PpmWriter {
+        PpmWriter { binary: false, tone_mapping }
+    }
+
+    pub fn binary(tone_mapping: ToneMapping) -> PpmWriter {
+        PpmWriter { binary: true, tone_mapping }
+    }
+}
+
+impl ImageWriter for PpmWriter {
+    fn write(&self, framebuffer: &Framebuffer, out: &mut dyn Write) -> Result<(), ImageError> {
+        //header required for ppm file
+        writeln!(out, "{}", if self.binary { "P6" } else { "P3" })?;
+        writeln!(out, "{} {}", framebuffer.width(), framebuffer.height())?;
+        writeln!(out, "255")?;
+
+        if self.binary {
+            let data: Vec<u8> = framebuffer.pixels().iter().flat_map(|pixel| self.tone_mapping.encode_rgb8(*pixel)).collect();
+            out.write_all(&data)?;
+        } else {
+            for pixel in framebuffer.pixels() {
+                let [r, g, b] = self.tone_mapping.encode_rgb8(*pixel);
+                writeln!(out, "{} {} {}", r, g, b)?;
+            }
+        }
+
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{ImageWriter, PpmWriter, ToneMapping};
+    use crate::output::test_support::framebuffer;
+
+    #[test]
+    fn ascii_and_binary_hold_the_same_pixels() {
+        let mut ascii = vec![];
+        let mut binary = vec![];
+        PpmWriter::ascii(ToneMapping::default()).write(&framebuffer(), &mut ascii).unwrap();
+        PpmWriter::binary(ToneMapping::default()).write(&framebuffer(), &mut binary).unwrap();
+
+        assert_eq!(String::from_utf8(ascii).unwrap(), "P3\n2 2\n255\n0 188 255\n255 118 3\n255 0 255\n137 0 0\n");
+        assert_eq!(binary, b"P6\n2 2\n255\n\x00\xbc\xff\xff\x76\x03\xff\x00\xff\x89\x00\x00");
+    }
+}
@@ -0,0 +1,242 @@
+use std::str::FromStr;
+use crate::interval::Interval;
+use crate::output::ImageError;
+use crate::vector3::Colour;
+
+// Curve used to squeeze unbounded scene radiance into the displayable [0, 1] range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapOperator {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    AcesFilmic,
+    Hable
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = ImageError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "clamp" | "none" => Ok(ToneMapOperator::Clamp),
+            "reinhard" => Ok(ToneMapOperator::Reinhard),
+            "extended-reinhard" | "reinhard-extended" => Ok(ToneMapOperator::ExtendedReinhard),
+            "aces" => Ok(ToneMapOperator::AcesFilmic),
+            "hable" | "uncharted" => Ok(ToneMapOperator::Hable),
+            _ => Err(ImageError::UnknownToneMapOperator(name.to_string()))
+        }
+    }
+}
+
+// Turns linear radiance into display values for the 8 and 16 bit formats: scales by the exposure,
+// applies the tone map operator, then encodes with the sRGB transfer function. The float formats
+// skip all of this and store the radiance as rendered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    pub exposure: f64, // in stops, each one doubles the brightness
+    pub white_point: f64 // smallest radiance mapped to pure white by extended Reinhard
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            operator: ToneMapOperator::Clamp,
+            exposure: 0.0,
+            white_point: 4.0
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapOperator, exposure: f64) -> ToneMapping {
+        ToneMapping {
+            operator,
+            exposure,
+            ..ToneMapping::default()
+        }
+    }
+
+    // Maps linear radiance to linear display values in [0, 1]
+    pub fn apply(&self, colour: Colour) -> Colour {
+        let colour = colour * 2f64.powf(self.exposure);
+
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => colour,
+            ToneMapOperator::Reinhard => scale_luminance(colour, |l| l / (1.0 + l)),
+            ToneMapOperator::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                scale_luminance(colour, |l| l * (1.0 + l / white_squared) / (1.0 + l))
+            }
+            ToneMapOperator::AcesFilmic => per_channel(colour, aces_filmic),
+            ToneMapOperator::Hable => {
+                let white_scale = 1.0 / hable_partial(HABLE_WHITE);
+                per_channel(colour, |x| hable_partial(x * HABLE_EXPOSURE_BIAS) * white_scale)
+            }
+        };
+
+        let display = Interval::new(0.0, 1.0);
+        Colour::new(display.clamp(mapped.x), display.clamp(mapped.y), display.clamp(mapped.z))
+    }
+
+    pub fn encode_rgb8(&self, colour: Colour) -> [u8; 3] {
+        let display = self.apply(colour);
+        let quantise = |component: f64| (srgb_encode(component) * 255.0).round() as u8;
+
+        [quantise(display.x), quantise(display.y), quantise(display.z)]
+    }
+
+    pub fn encode_rgb16(&self, colour: Colour) -> [u16; 3] {
+        let display = self.apply(colour);
+        let quantise = |component: f64| (srgb_encode(component) * 65535.0).round() as u16;
+
+        [quantise(display.x), quantise(display.y), quantise(display.z)]
+    }
+}
+
+// sRGB transfer function, turning a linear value in [0, 1] into the encoded value displays expect
+pub fn srgb_encode(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn luminance(colour: Colour) -> f64 {
+    0.2126 * colour.x + 0.7152 * colour.y + 0.0722 * colour.z
+}
+
+// Tone maps the luminance and scales the colour to match, which keeps the hue of bright colours
+// instead of washing them out to white channel by channel
+fn scale_luminance(colour: Colour, curve: impl Fn(f64) -> f64) -> Colour {
+    let l = luminance(colour);
+    if l <= 0.0 {
+        return Colour::new(0.0, 0.0, 0.0);
+    }
+
+    colour * (curve(l) / l)
+}
+
+fn per_channel(colour: Colour, curve: impl Fn(f64) -> f64) -> Colour {
+    Colour::new(curve(colour.x), curve(colour.y), curve(colour.z))
+}
+
+// Krzysztof Narkowicz's curve fit of the ACES filmic reference rendering transform
+fn aces_filmic(x: f64) -> f64 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+const HABLE_EXPOSURE_BIAS: f64 = 2.0;
+const HABLE_WHITE: f64 = 11.2;
+
+// John Hable's filmic curve from Uncharted 2
+fn hable_partial(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::tonemap::{luminance, srgb_encode, ToneMapOperator, ToneMapping};
+    use crate::vector3::Colour;
+
+    fn grey(value: f64) -> Colour {
+        Colour::new(value, value, value)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn srgb_encode_matches_known_values() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert_close(srgb_encode(1.0), 1.0);
+        assert_close(srgb_encode(0.5), 0.735357);
+        assert_close(srgb_encode(0.002), 0.02584);
+
+        // the linear and power segments meet at the breakpoint
+        assert_close(srgb_encode(0.0031308), 0.04045);
+        assert!((srgb_encode(0.0031308) - srgb_encode(0.0031308 + 1e-9)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn srgb_encode_round_trips_through_the_decoder() {
+        // the decoding curve the image loaders use
+        let decode = |encoded: f64| if encoded <= 0.04045 { encoded / 12.92 } else { ((encoded + 0.055) / 1.055).powf(2.4) };
+
+        for step in 0..=100 {
+            let linear = step as f64 / 100.0;
+            assert_close(decode(srgb_encode(linear)), linear);
+        }
+    }
+
+    #[test]
+    fn quantises_to_8_and_16_bits() {
+        let tone_mapping = ToneMapping::default();
+
+        assert_eq!(tone_mapping.encode_rgb8(Colour::new(0.0, 0.5, 1.0)), [0, 188, 255]);
+        assert_eq!(tone_mapping.encode_rgb16(Colour::new(0.0, 0.5, 1.0)), [0, 48192, 65535]);
+
+        // out of range values clamp rather than wrap
+        assert_eq!(tone_mapping.encode_rgb8(Colour::new(-1.0, 7.0, f64::INFINITY)), [0, 255, 255]);
+    }
+
+    #[test]
+    fn exposure_scales_by_stops() {
+        let brighter = ToneMapping::new(ToneMapOperator::Clamp, 1.0);
+        let darker = ToneMapping::new(ToneMapOperator::Clamp, -2.0);
+
+        assert_close(brighter.apply(grey(0.25)).x, 0.5);
+        assert_close(darker.apply(grey(0.8)).x, 0.2);
+    }
+
+    #[test]
+    fn operators_match_known_values() {
+        let apply = |operator, value| ToneMapping::new(operator, 0.0).apply(grey(value)).x;
+
+        assert_close(apply(ToneMapOperator::Clamp, 0.3), 0.3);
+        assert_close(apply(ToneMapOperator::Clamp, 3.0), 1.0);
+
+        assert_close(apply(ToneMapOperator::Reinhard, 1.0), 0.5);
+        assert_close(apply(ToneMapOperator::Reinhard, 3.0), 0.75);
+
+        // extended Reinhard reaches white exactly at the white point
+        assert_close(apply(ToneMapOperator::ExtendedReinhard, 4.0), 1.0);
+        assert_close(apply(ToneMapOperator::ExtendedReinhard, 1.0), 0.53125);
+
+        assert_close(apply(ToneMapOperator::AcesFilmic, 0.0), 0.0);
+        assert_close(apply(ToneMapOperator::AcesFilmic, 1.0), 2.54 / 3.16);
+        assert_close(apply(ToneMapOperator::AcesFilmic, 100.0), 1.0);
+
+        // Hable maps its white point, before the exposure bias, to white
+        assert_close(apply(ToneMapOperator::Hable, 0.0), 0.0);
+        assert_close(apply(ToneMapOperator::Hable, 5.6), 1.0);
+    }
+
+    #[test]
+    fn operators_are_monotonic() {
+        for operator in [ToneMapOperator::Reinhard, ToneMapOperator::ExtendedReinhard, ToneMapOperator::AcesFilmic, ToneMapOperator::Hable] {
+            let tone_mapping = ToneMapping::new(operator, 0.0);
+            let mut previous = 0.0;
+
+            for step in 1..200 {
+                let value = tone_mapping.apply(grey(step as f64 * 0.05)).x;
+                assert!(value >= previous, "{:?} drops at {}", operator, step as f64 * 0.05);
+                previous = value;
+            }
+        }
+    }
+
+    #[test]
+    fn reinhard_keeps_hue() {
+        let colour = Colour::new(2.0, 1.0, 0.5);
+        let mapped = ToneMapping::new(ToneMapOperator::Reinhard, 0.0).apply(colour);
+
+        assert_close(mapped.x / mapped.y, 2.0);
+        assert_close(mapped.y / mapped.z, 2.0);
+        assert_close(luminance(mapped), luminance(colour) / (1.0 + luminance(colour)));
+    }
+}
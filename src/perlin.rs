@@ -0,0 +1,156 @@
+use crate::sampler::Sampler;
+use crate::vector3::{random_unit_vector, Point3, Vector3};
+
+const POINT_COUNT: usize = 256;
+
+// Ken Perlin's gradient noise: every point of the integer lattice gets a random unit gradient,
+// picked by hashing its coordinates through three shuffled permutation tables, and the noise
+// between lattice points is a smoothed trilinear blend of the eight surrounding gradients' dot
+// products. The same seed always gives the same noise.
+pub struct Perlin {
+    gradients: Vec<Vector3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut sampler = Sampler::new(seed);
+        let gradients = (0..POINT_COUNT).map(|_| random_unit_vector(&mut sampler)).collect();
+
+        Perlin {
+            gradients,
+            perm_x: permutation(&mut sampler),
+            perm_y: permutation(&mut sampler),
+            perm_z: permutation(&mut sampler)
+        }
+    }
+
+    // smooth noise roughly between -1 and 1, zero at every lattice point
+    pub fn noise(&self, point: Point3) -> f64 {
+        let (i, u) = split(point.x);
+        let (j, v) = split(point.y);
+        let (k, w) = split(point.z);
+
+        let mut corners = [[[Vector3::new(0.0, 0.0, 0.0); 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[wrap(i + di as i64)] ^ self.perm_y[wrap(j + dj as i64)] ^ self.perm_z[wrap(k + dk as i64)];
+                    *corner = self.gradients[index];
+                }
+            }
+        }
+
+        interpolate(&corners, u, v, w)
+    }
+
+    // Fractal sum of the noise's magnitude over octaves, each at twice the frequency and half the
+    // weight of the last. Always positive, and mostly below 1.
+    pub fn turbulence(&self, point: Point3, octaves: u32) -> f64 {
+        let mut accumulated = 0.0;
+        let mut point = point;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            accumulated += weight * self.noise(point).abs();
+            weight *= 0.5;
+            point = point * 2.0;
+        }
+
+        accumulated
+    }
+}
+
+// a random shuffle of 0..POINT_COUNT
+fn permutation(sampler: &mut Sampler) -> Vec<usize> {
+    let mut values: Vec<usize> = (0..POINT_COUNT).collect();
+
+    for i in (1..POINT_COUNT).rev() {
+        let target = ((sampler.random() * (i + 1) as f64) as usize).min(i);
+        values.swap(i, target);
+    }
+
+    values
+}
+
+// the lattice cell a coordinate is in, and how far across the cell it is
+fn split(coordinate: f64) -> (i64, f64) {
+    let floor = coordinate.floor();
+
+    (floor as i64, coordinate - floor)
+}
+
+fn wrap(index: i64) -> usize {
+    index.rem_euclid(POINT_COUNT as i64) as usize
+}
+
+fn interpolate(corners: &[[[Vector3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+    // Hermite smoothing hides the lattice by making the blend's slope zero at the cell edges
+    let uu = u * u * (3.0 - 2.0 * u);
+    let vv = v * v * (3.0 - 2.0 * v);
+    let ww = w * w * (3.0 - 2.0 * w);
+
+    let mut accumulated = 0.0;
+    for (i, plane) in corners.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, gradient) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                let offset = Vector3::new(u - fi, v - fj, w - fk);
+
+                accumulated += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                    * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                    * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                    * gradient.dot(offset);
+            }
+        }
+    }
+
+    accumulated
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interval::Interval;
+    use crate::perlin::Perlin;
+    use crate::sampler::Sampler;
+    use crate::vector3::{random_in_interval, Point3};
+
+    fn random_points(count: usize) -> Vec<Point3> {
+        let mut sampler = Sampler::new(3);
+        (0..count).map(|_| random_in_interval(Interval::new(-300.0, 300.0), &mut sampler)).collect()
+    }
+
+    #[test]
+    fn seeds_decide_the_noise() {
+        let (a, b, c) = (Perlin::new(1), Perlin::new(1), Perlin::new(2));
+        let points = random_points(100);
+
+        assert!(points.iter().all(|&point| a.noise(point) == b.noise(point)));
+        assert!(points.iter().all(|&point| a.turbulence(point, 7) == b.turbulence(point, 7)));
+
+        let different = points.iter().filter(|&&point| a.noise(point) != c.noise(point)).count();
+        assert!(different > 90, "only {} points differ", different);
+    }
+
+    #[test]
+    fn noise_is_zero_on_the_lattice() {
+        let noise = Perlin::new(5);
+
+        for point in random_points(100) {
+            let lattice = Point3::new(point.x.round(), point.y.round(), point.z.round());
+            assert_eq!(noise.noise(lattice), 0.0, "{:?}", (lattice.x, lattice.y, lattice.z));
+        }
+    }
+
+    #[test]
+    fn noise_stays_within_one() {
+        let noise = Perlin::new(9);
+        let values: Vec<f64> = random_points(20000).into_iter().map(|point| noise.noise(point)).collect();
+
+        assert!(values.iter().all(|value| (-1.0..=1.0).contains(value)));
+        // and actually uses the range, rather than being stuck near zero
+        assert!(values.iter().any(|&value| value > 0.4) && values.iter().any(|&value| value < -0.4));
+    }
+}
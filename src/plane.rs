@@ -0,0 +1,109 @@
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::vector3::*;
+
+// An infinite plane through a point, for ground and water that has to reach the horizon. Its
+// surface coordinates are distances in world units along two perpendicular directions in the
+// plane, so they go on forever too: solid textures suit it better than images. Having no area,
+// it can't be sampled as a light.
+pub struct Plane {
+    point: Point3,
+    normal: Vector3,
+    frame: Onb,
+    material: Arc<dyn Material>
+}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vector3, material: Arc<dyn Material>) -> Plane {
+        let frame = Onb::new(normal);
+
+        Plane { point, normal: frame.w, frame, material }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let t = intersect_plane(ray, self.point, self.normal, ray_t)?;
+        let point = ray.at(t);
+
+        let mut record = HitRecord::new(point, self.normal, t, Arc::clone(&self.material));
+        record.set_face_normal(ray, self.normal);
+
+        let offset = point - self.point;
+        record.u = offset.dot(self.frame.u);
+        record.v = offset.dot(self.frame.v);
+
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        record.set_derivatives(self.frame.u, self.frame.v, zero, zero);
+
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(Interval::UNIVERSE, Interval::UNIVERSE, Interval::UNIVERSE)
+    }
+}
+
+// Where a ray crosses the plane through point with the given normal, if it does inside ray_t.
+// Rays running parallel to the plane never cross it.
+pub fn intersect_plane(ray: &Ray, point: Point3, normal: Vector3, ray_t: Interval) -> Option<f64> {
+    let denominator = normal.dot(ray.direction);
+    if denominator.abs() < 1e-8 {
+        return None;
+    }
+
+    let t = normal.dot(point - ray.origin) / denominator;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+
+    Some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::material::Matte;
+    use crate::plane::Plane;
+    use crate::ray::Ray;
+    use crate::vector3::{Colour, Point3, Vector3};
+
+    // the y = 1 plane, facing up
+    fn plane() -> Plane {
+        let material = Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)));
+        Plane::new(Point3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 2.0, 0.0), material)
+    }
+
+    #[test]
+    fn parallel_rays_miss() {
+        let everywhere = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+
+        for origin in [Point3::new(0.0, 3.0, 0.0), Point3::new(5.0, 1.0, -2.0)] {
+            let ray = Ray::new(origin, Vector3::new(1.0, 0.0, 1.0));
+            assert!(plane().hit(&ray, everywhere).is_none());
+        }
+    }
+
+    #[test]
+    fn hits_from_either_side_say_which() {
+        let above = Ray::new(Point3::new(1e5, 4.0, -3e5), Vector3::new(0.5, -1.0, 0.0));
+        let below = Ray::new(Point3::new(0.0, -2.0, 0.0), Vector3::new(0.0, 1.0, 0.2));
+
+        let record = plane().hit(&above, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!(record.front_face);
+        assert_eq!((record.normal.x, record.normal.y, record.normal.z), (0.0, 1.0, 0.0));
+        assert!((record.point.y - 1.0).abs() < 1e-9 && (record.t - 3.0).abs() < 1e-9);
+
+        let record = plane().hit(&below, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!(!record.front_face);
+        assert_eq!((record.normal.x, record.normal.y, record.normal.z), (0.0, -1.0, 0.0));
+        assert!((record.point.y - 1.0).abs() < 1e-12 && (record.t - 3.0).abs() < 1e-12);
+    }
+}
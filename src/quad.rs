@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::plane::intersect_plane;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::triangle::area_pdf;
+use crate::vector3::*;
+
+// A parallelogram with one corner at corner and the edges u and v leaving it. Surface
+// coordinates run from 0 to 1 along each edge, and the normal faces the side from which u turns
+// counter-clockwise into v. The edges can't be parallel (or zero), which would leave no area and
+// no normal: scene files check that before getting here.
+pub struct Quad {
+    corner: Point3,
+    u: Vector3,
+    v: Vector3,
+    normal: Vector3,
+    w: Vector3, // n / (n . n) for the unnormalised normal n = u x v, for finding the coordinates of hits
+    area: f64,
+    material: Arc<dyn Material>,
+    bbox: Aabb
+}
+
+impl Quad {
+    pub fn new(corner: Point3, u: Vector3, v: Vector3, material: Arc<dyn Material>) -> Quad {
+        let n = u.cross(v);
+        assert!(!n.near_zero(), "a quad's edges can't be parallel");
+        let bbox = Aabb::union(Aabb::from_points(corner, corner + u + v), Aabb::from_points(corner + u, corner + v));
+
+        Quad {
+            corner,
+            u,
+            v,
+            normal: n.unit(),
+            w: n / n.dot(n),
+            area: n.length(),
+            material,
+            bbox
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let t = intersect_plane(ray, self.corner, self.normal, ray_t)?;
+        let point = ray.at(t);
+
+        // the hit's coordinates along u and v
+        let planar = point - self.corner;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let mut record = HitRecord::new(point, self.normal, t, Arc::clone(&self.material));
+        record.set_face_normal(ray, self.normal);
+        record.u = alpha;
+        record.v = beta;
+
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        record.set_derivatives(self.u, self.v, zero, zero);
+
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // sampled uniformly by area
+    fn pdf_value(&self, origin: Point3, direction: Vector3) -> f64 {
+        let ray = Ray::new(origin, direction);
+        match self.hit(&ray, Interval::new(0.001, f64::INFINITY)) {
+            Some(record) => area_pdf(&ray, record.t, record.normal, self.area),
+            None => 0.0
+        }
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut Sampler) -> Vector3 {
+        let point = self.corner + self.u * sampler.random() + self.v * sampler.random();
+
+        point - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::hittable::Hittable;
+    use crate::hittable::test_support::{pdf_integral, solid_angles};
+    use crate::interval::Interval;
+    use crate::material::Matte;
+    use crate::quad::Quad;
+    use crate::ray::Ray;
+    use crate::vector3::{Colour, Point3, Vector3};
+
+    // 2 wide and 1 high in the z = 0 plane, facing +z
+    fn quad() -> Quad {
+        let material = Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)));
+        Quad::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(2.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), material)
+    }
+
+    // the surface coordinates where a ray straight down onto (x, y) hits, if it does
+    fn uv_at(x: f64, y: f64) -> Option<(f64, f64)> {
+        let ray = Ray::new(Point3::new(x, y, 1.0), Vector3::new(0.0, 0.0, -1.0));
+        quad().hit(&ray, Interval::new(0.001, f64::INFINITY)).map(|record| (record.u, record.v))
+    }
+
+    #[test]
+    fn hits_inside_and_on_the_edges_only() {
+        assert_eq!(uv_at(1.0, 0.5), Some((0.5, 0.5)));
+        assert_eq!(uv_at(2.0, 0.5), Some((1.0, 0.5)));
+        assert_eq!(uv_at(1.0, 0.0), Some((0.5, 0.0)));
+
+        for (x, y) in [(2.01, 0.5), (-0.01, 0.5), (1.0, 1.01), (1.0, -0.01), (3.0, 3.0)] {
+            assert_eq!(uv_at(x, y), None, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn corners_have_the_corner_coordinates() {
+        assert_eq!(uv_at(0.0, 0.0), Some((0.0, 0.0)));
+        assert_eq!(uv_at(2.0, 0.0), Some((1.0, 0.0)));
+        assert_eq!(uv_at(0.0, 1.0), Some((0.0, 1.0)));
+        assert_eq!(uv_at(2.0, 1.0), Some((1.0, 1.0)));
+    }
+
+    #[test]
+    fn sampling_matches_its_density() {
+        let quad = quad();
+
+        for origin in [Point3::new(1.0, 0.5, 1.0), Point3::new(2.5, -0.5, 1.0)] {
+            let (uniform, sampled) = solid_angles(&quad, origin);
+            assert!((uniform - sampled).abs() < 0.03 * uniform, "{} against {}", uniform, sampled);
+
+            let integral = pdf_integral(&quad, origin);
+            assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "parallel")]
+    fn parallel_edges_are_rejected() {
+        let material = Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)));
+        Quad::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 0.0), Vector3::new(-2.0, -2.0, 0.0), material);
+    }
+}
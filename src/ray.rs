@@ -3,12 +3,28 @@ use crate::vector3::{Vector3, Point3};
 #[derive(Clone, Copy, Debug)]
 pub struct Ray {
     pub origin: Point3,
-    pub direction: Vector3
+    pub direction: Vector3,
+    pub differentials: Option<RayDifferentials>
+}
+
+// Two neighbouring rays, offset by about the spacing between camera samples in the image's x
+// and y directions. Following them along with the main ray shows how big a patch of surface the
+// ray stands for, which is what textures need to filter away detail finer than a sample.
+#[derive(Clone, Copy, Debug)]
+pub struct RayDifferentials {
+    pub rx_origin: Point3,
+    pub rx_direction: Vector3,
+    pub ry_origin: Point3,
+    pub ry_direction: Vector3
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vector3) -> Ray {
-        Ray { origin, direction }
+        Ray { origin, direction, differentials: None }
+    }
+
+    pub fn with_differentials(origin: Point3, direction: Vector3, differentials: RayDifferentials) -> Ray {
+        Ray { origin, direction, differentials: Some(differentials) }
     }
 
     //get origin/direction
@@ -21,4 +37,4 @@ impl Ray {
     pub fn at(self, t:f64) -> Vector3 {
         self.origin + self.direction*t
     }
-}
\ No newline at end of file
+}
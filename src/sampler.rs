@@ -0,0 +1,35 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::interval::Interval;
+use crate::util;
+
+// Source of random numbers for everything that needs them (camera rays, materials, scene
+// generators). Always created from an explicit seed so a render can be reproduced exactly.
+pub struct Sampler {
+    rng: StdRng
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Sampler {
+        Sampler {
+            rng: StdRng::seed_from_u64(seed)
+        }
+    }
+
+    // Gives every pixel its own stream derived from the render seed, so a pixel gets the same
+    // samples no matter which thread renders it or in what order the tiles are handed out
+    pub fn for_pixel(seed: u64, x: u32, y: u32) -> Sampler {
+        let pixel = ((y as u64) << 32) | x as u64;
+
+        Sampler::new(util::mix_seed(seed, pixel))
+    }
+
+    // random real in [0, 1)
+    pub fn random(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    pub fn random_in_interval(&mut self, range : Interval) -> f64 {
+        range.min + (range.max - range.min) * self.random()
+    }
+}
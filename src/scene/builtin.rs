@@ -0,0 +1,141 @@
+use std::sync::Arc;
+use crate::camera::{Background, CameraSettings};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::light::Lights;
+use crate::material::{Dielectric, DiffuseLight, Material, Matte, Metal};
+use crate::quad::Quad;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::sphere::Sphere;
+use crate::vector3::{self, Colour, Point3, Vector3};
+
+// Names accepted by builtin_scene, for help text and error messages
+pub const BUILTIN_SCENES: [&str; 3] = ["random", "three-spheres", "cornell"];
+
+// Scenes written in Rust rather than loaded from a file. Randomised ones are generated from seed.
+pub fn builtin_scene(name: &str, seed: u64) -> Option<Scene> {
+    let scene = match name {
+        "random" => Scene { world: random_scene(&mut Sampler::new(seed)), lights: Lights::new(), camera: CameraSettings::default() },
+        "three-spheres" => Scene { world: three_spheres(), lights: Lights::new(), camera: CameraSettings::default() },
+        "cornell" => cornell_box(),
+        _ => return None
+    };
+
+    Some(scene)
+}
+
+// The final scene of the book: a field of small random spheres around three large ones
+pub fn random_scene(sampler: &mut Sampler) -> HittableList {
+    let mut world = HittableList::new();
+    add_ground(&mut world);
+
+    let some_point = Point3::new(4.0, 0.2, 0.0);
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = sampler.random();
+            let center = Point3::new(a as f64 + 0.9 * sampler.random(), 0.2, b as f64 + 0.9 * sampler.random());
+
+            if (center - some_point).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = vector3::random(sampler) * vector3::random(sampler);
+                    let sphere_material = Matte::new(albedo);
+                    let sphere = Sphere::new(center, 0.2, Arc::new(sphere_material));
+                    world.add(Box::new(sphere));
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = vector3::random_in_interval(Interval::new(0.0, 0.5), sampler);
+                    let fuzz = sampler.random_in_interval(Interval::new(0.0, 0.5));
+                    let sphere_material = Metal::new(albedo, fuzz);
+                    let sphere = Sphere::new(center, 0.2, Arc::new(sphere_material));
+                    world.add(Box::new(sphere));
+                } else {
+                    // glass
+                    let sphere_material = Dielectric::new(1.5);
+                    let sphere = Sphere::new(center, 0.2, Arc::new(sphere_material));
+                    world.add(Box::new(sphere));
+                }
+            }
+        }
+    }
+
+    add_large_spheres(&mut world);
+
+    world
+}
+
+// The three large spheres of the random scene on their own
+fn three_spheres() -> HittableList {
+    let mut world = HittableList::new();
+    add_ground(&mut world);
+    add_large_spheres(&mut world);
+
+    world
+}
+
+fn add_ground(world: &mut HittableList) {
+    let ground_material = Matte::new(Colour::new(0.5, 0.5, 0.5));
+    let sphere = Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(ground_material),
+    );
+    world.add(Box::new(sphere));
+}
+
+fn add_large_spheres(world: &mut HittableList) {
+    let sphere_material = Matte::new(Colour::new(0.9, 0.2, 0.3));
+    let sphere = Sphere::new(Vector3::new(-4.0, 1.0, 0.0), 1.0, Arc::new(sphere_material));
+    world.add(Box::new(sphere));
+
+    let sphere_material = Dielectric::new(1.5);
+    let sphere = Sphere::new(Vector3::new(0.0, 1.0, 0.0), 1.0, Arc::new(sphere_material));
+    world.add(Box::new(sphere));
+
+    let sphere_material = Metal::new(Colour::new(0.7, 0.6, 0.5), 0.0);
+    let sphere = Sphere::new(Vector3::new(4.0, 1.0, 0.0), 1.0, Arc::new(sphere_material));
+    world.add(Box::new(sphere));
+}
+
+// The classic Cornell box, lit only by the light in its ceiling, with a diffuse and a glass sphere
+pub fn cornell_box() -> Scene {
+    let camera = CameraSettings {
+        aspect_ratio: 1.0,
+        image_width: 600,
+        samples_per_pixel: 200,
+        max_depth: 50,
+        vfov: 40.0,
+        look_from: Point3::new(278.0, 278.0, -800.0),
+        look_at: Point3::new(278.0, 278.0, 0.0),
+        defocus_angle: 0.0,
+        background: Background::Solid(Colour::new(0.0, 0.0, 0.0)),
+        ..CameraSettings::default()
+    };
+
+    let mut scene = Scene::new(camera);
+
+    let red: Arc<dyn Material> = Arc::new(Matte::new(Colour::new(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Material> = Arc::new(Matte::new(Colour::new(0.73, 0.73, 0.73)));
+    let green: Arc<dyn Material> = Arc::new(Matte::new(Colour::new(0.12, 0.45, 0.15)));
+    let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Colour::new(15.0, 15.0, 15.0)));
+
+    let walls = [
+        (Point3::new(555.0, 0.0, 0.0), Vector3::new(0.0, 555.0, 0.0), Vector3::new(0.0, 0.0, 555.0), &green),
+        (Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 555.0, 0.0), Vector3::new(0.0, 0.0, 555.0), &red),
+        (Point3::new(0.0, 0.0, 0.0), Vector3::new(555.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 555.0), &white),
+        (Point3::new(555.0, 555.0, 555.0), Vector3::new(-555.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -555.0), &white),
+        (Point3::new(0.0, 0.0, 555.0), Vector3::new(555.0, 0.0, 0.0), Vector3::new(0.0, 555.0, 0.0), &white)
+    ];
+
+    for (corner, u, v, material) in walls {
+        scene.add(Arc::new(Quad::new(corner, u, v, Arc::clone(material))));
+    }
+
+    scene.add_light(Arc::new(Quad::new(Point3::new(343.0, 554.0, 332.0), Vector3::new(-130.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -105.0), light)));
+
+    scene.add(Arc::new(Sphere::new(Point3::new(190.0, 90.0, 190.0), 90.0, Arc::clone(&white))));
+    scene.add(Arc::new(Sphere::new(Point3::new(370.0, 90.0, 370.0), 90.0, Arc::new(Dielectric::new(1.5)))));
+
+    scene
+}
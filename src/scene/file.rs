@@ -0,0 +1,619 @@
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use serde::Deserialize;
+use toml::Spanned;
+use crate::camera::{Background, CameraSettings};
+use crate::cuboid::Cuboid;
+use crate::disk::Disk;
+use crate::hittable::Hittable;
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::loaders;
+use crate::mipmap::TextureFilter;
+use crate::plane::Plane;
+use crate::quad::Quad;
+use crate::material::{Dielectric, DiffuseLight, Material, Matte, Metal};
+use crate::scene::{Scene, SceneError};
+use crate::sphere::Sphere;
+use crate::texture::{Checker, ColourRamp, ImageTexture, NoisePattern, NoiseTexture, SolidColour, Texture};
+use crate::triangle::Triangle;
+use crate::vector3::{Colour, Point3, Vector3};
+
+// Scene files are TOML, for example:
+//
+//   [camera]
+//   image_width = 800
+//   look_from = [13, 2, 3]
+//   look_at = [0, 0, 0]
+//
+//   [materials.ground]
+//   type = "matte"
+//   albedo = [0.5, 0.5, 0.5]
+//
+//   [[objects]]
+//   type = "sphere"
+//   center = [0, -1000, 0]
+//   radius = 1000
+//   material = "ground"
+//
+// Every camera setting is optional and falls back to CameraSettings::default(), and the camera's
+// background is either "sky" or a colour.
+//
+// Materials are "matte" (albedo), "metal" (albedo, fuzz), "dielectric" (ior) or "light" (emit),
+// where an albedo is a colour or the name of a texture. Textures are:
+//   "solid"      colour
+//   "checker"    scale, even, odd: colours or the names of textures that aren't checkers
+//   "image"      path to a PNG or PPM, optional filter "bilinear", "trilinear" or "ewa"
+//   "turbulence", "marble", "wood" and "granite"
+//                Perlin noise patterns with optional scale, turbulence, seed and ramp, a list of
+//                { at, colour } stops
+//
+// Objects are:
+//   "sphere"     center, radius, material
+//   "triangle"   vertices, material
+//   "quad"       corner, u and v edges, material
+//   "disk"       center, normal, radius, material
+//   "plane"      point, normal, material
+//   "box"        corners (two opposite ones), material, and optionally angle, in degrees to
+//                turn it about its centre around axis, which defaults to [0, 1, 0]
+//   "mesh"       path, optional default material
+//   "gltf"       path
+// with paths relative to the scene file. Objects with a light material are sampled as area
+// lights, except planes: being infinite, they only light what happens to hit them.
+//
+// Analytic lights go in [[lights]] tables: "point" (position, intensity), "spot" (position,
+// direction, intensity, inner_angle, outer_angle in degrees) and "directional" (direction,
+// irradiance), where directions are the way the light travels.
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|error| SceneError::io(path, error))?;
+
+    parse_scene(&source, path)
+}
+
+// Builds a scene from the contents of a scene file, where path is only used to report errors and
+// to find the files that objects refer to
+pub fn parse_scene(source: &str, path: &Path) -> Result<Scene, SceneError> {
+    let file: SceneFile = toml::from_str(source).map_err(|error| {
+        let line = error.span().map_or(1, |span| line_of(source, span));
+        SceneError::invalid(path, line, error.message().trim())
+    })?;
+
+    let invalid = |span: Range<usize>, message: String| SceneError::invalid(path, line_of(source, span), message);
+
+    let camera = match file.camera {
+        Some(camera) => camera_settings(camera, &invalid)?,
+        None => CameraSettings::default()
+    };
+
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    // checkers are built after everything else, so they can use any texture but another checker
+    let (checkers, others): (Vec<_>, Vec<_>) = file.textures.into_iter()
+        .partition(|(_, table)| matches!(table.get_ref(), TextureTable::Checker { .. }));
+
+    let mut textures: HashMap<String, Arc<dyn Texture>> = HashMap::new();
+    for (name, table) in others {
+        let texture = build_texture(&name, table, base, &textures, &invalid)?;
+        textures.insert(name, texture);
+    }
+
+    let mut built_checkers = vec![];
+    for (name, table) in checkers {
+        let texture = build_texture(&name, table, base, &textures, &invalid)?;
+        built_checkers.push((name, texture));
+    }
+    textures.extend(built_checkers);
+
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    for (name, table) in file.materials {
+        let span = table.span();
+        let material = build_material(table.into_inner(), &textures).map_err(|message| invalid(span, format!("material '{}': {}", name, message)))?;
+        materials.insert(name, material);
+    }
+
+    let find_material = |name: &str, span: Range<usize>| match materials.get(name) {
+        Some(material) => Ok(Arc::clone(material)),
+        None => Err(invalid(span, format!("unknown material '{}'", name)))
+    };
+
+    let mut scene = Scene::new(camera);
+
+    for object in file.objects {
+        let span = object.span();
+
+        match object.into_inner() {
+            ObjectTable::Sphere { center, radius, material } => {
+                if !(radius.is_finite() && radius > 0.0) {
+                    return Err(invalid(span, format!("sphere radius must be positive, not {}", radius)));
+                }
+
+                let material = find_material(&material, span)?;
+                let emits_light = material.is_emissive();
+                add(&mut scene, Arc::new(Sphere::new(point(center), radius, material)), emits_light);
+            }
+            ObjectTable::Triangle { vertices: [v0, v1, v2], material } => {
+                let material = find_material(&material, span)?;
+                let emits_light = material.is_emissive();
+                add(&mut scene, Arc::new(Triangle::new(point(v0), point(v1), point(v2), material)), emits_light);
+            }
+            ObjectTable::Quad { corner, u, v, material } => {
+                if point(u).cross(point(v)).near_zero() {
+                    return Err(invalid(span, "quad edges u and v can't be parallel or zero".to_string()));
+                }
+
+                let material = find_material(&material, span)?;
+                let emits_light = material.is_emissive();
+                add(&mut scene, Arc::new(Quad::new(point(corner), point(u), point(v), material)), emits_light);
+            }
+            ObjectTable::Disk { center, normal, radius, material } => {
+                if !(radius.is_finite() && radius > 0.0) {
+                    return Err(invalid(span, format!("disk radius must be positive, not {}", radius)));
+                }
+                if point(normal).near_zero() {
+                    return Err(invalid(span, "disk normal can't be zero".to_string()));
+                }
+
+                let material = find_material(&material, span)?;
+                let emits_light = material.is_emissive();
+                add(&mut scene, Arc::new(Disk::new(point(center), point(normal), radius, material)), emits_light);
+            }
+            ObjectTable::Plane { point: origin, normal, material } => {
+                if point(normal).near_zero() {
+                    return Err(invalid(span, "plane normal can't be zero".to_string()));
+                }
+
+                // there's no sampling an infinite plane, so it's only ever added to the world
+                let material = find_material(&material, span)?;
+                scene.add(Arc::new(Plane::new(point(origin), point(normal), material)));
+            }
+            ObjectTable::Box { corners: [a, b], material, angle, axis } => {
+                if (0..3).any(|axis| a[axis] == b[axis]) {
+                    return Err(invalid(span, "box corners need to differ along every axis".to_string()));
+                }
+
+                let axis = point(axis.unwrap_or([0.0, 1.0, 0.0]));
+                if axis.near_zero() {
+                    return Err(invalid(span, "box rotation axis can't be zero".to_string()));
+                }
+
+                let material = find_material(&material, span)?;
+                let emits_light = material.is_emissive();
+                add(&mut scene, Arc::new(Cuboid::rotated(point(a), point(b), axis, angle, material)), emits_light);
+            }
+            ObjectTable::Mesh { path, material } => {
+                let material = match material {
+                    Some(name) => find_material(&name, span)?,
+                    None => Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)))
+                };
+
+                let mesh = loaders::load_mesh(&base.join(path), material)?;
+                let emits_light = mesh.emits_light();
+                add(&mut scene, Arc::new(mesh), emits_light);
+            }
+            ObjectTable::Gltf { path } => {
+                let gltf = loaders::gltf::load_gltf(&base.join(path))?;
+                for object in gltf.world.into_objects() {
+                    scene.world.add_shared(object);
+                }
+                for light in gltf.lights.into_objects() {
+                    scene.lights.add_area(light);
+                }
+            }
+        }
+    }
+
+    for light in file.lights {
+        let span = light.span();
+        let light = build_light(light.into_inner()).map_err(|message| invalid(span, message))?;
+        scene.add_analytic_light(light);
+    }
+
+    Ok(scene)
+}
+
+// anything that glows also goes in the scene's lights so it gets sampled directly
+fn add(scene: &mut Scene, object: Arc<dyn Hittable>, emits_light: bool) {
+    if emits_light {
+        scene.add_light(object);
+    } else {
+        scene.add(object);
+    }
+}
+
+type Triple = [f64; 3];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    camera: Option<Spanned<CameraTable>>,
+    #[serde(default)]
+    textures: HashMap<String, Spanned<TextureTable>>,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<MaterialTable>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectTable>>,
+    #[serde(default)]
+    lights: Vec<Spanned<LightTable>>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraTable {
+    aspect_ratio: Option<f64>,
+    image_width: Option<u32>,
+    samples_per_pixel: Option<u32>,
+    max_depth: Option<u32>,
+    vfov: Option<f64>,
+    look_from: Option<Triple>,
+    look_at: Option<Spanned<Triple>>,
+    vup: Option<Triple>,
+    defocus_angle: Option<f64>,
+    focus_dist: Option<f64>,
+    background: Option<Spanned<BackgroundValue>>
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BackgroundValue {
+    Named(String),
+    Colour(Triple)
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum TextureTable {
+    Solid { colour: Triple },
+    Checker { scale: f64, even: TextureValue, odd: TextureValue },
+    Image { path: String, filter: Option<FilterValue> },
+    Turbulence(NoiseTable),
+    Marble(NoiseTable),
+    Wood(NoiseTable),
+    Granite(NoiseTable)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FilterValue {
+    Bilinear,
+    Trilinear,
+    Ewa
+}
+
+// anything left out takes the pattern's default
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseTable {
+    #[serde(default = "unit_scale")]
+    scale: f64,
+    turbulence: Option<f64>,
+    #[serde(default)]
+    seed: u64,
+    ramp: Option<Vec<RampStop>>
+}
+
+fn unit_scale() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RampStop {
+    at: f64,
+    colour: Triple
+}
+
+// a plain colour, or the name of a texture
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureValue {
+    Colour(Triple),
+    Named(String)
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum MaterialTable {
+    Matte { albedo: TextureValue },
+    Metal { albedo: TextureValue, #[serde(default)] fuzz: f64 },
+    Dielectric { ior: f64 },
+    Light { emit: Triple }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ObjectTable {
+    Sphere { center: Triple, radius: f64, material: String },
+    Triangle { vertices: [Triple; 3], material: String },
+    Quad { corner: Triple, u: Triple, v: Triple, material: String },
+    Disk { center: Triple, normal: Triple, radius: f64, material: String },
+    Plane { point: Triple, normal: Triple, material: String },
+    Box { corners: [Triple; 2], material: String, #[serde(default)] angle: f64, axis: Option<Triple> },
+    Mesh { path: String, material: Option<String> },
+    Gltf { path: String }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum LightTable {
+    Point { position: Triple, intensity: Triple },
+    Spot { position: Triple, direction: Triple, intensity: Triple, #[serde(default)] inner_angle: f64, outer_angle: f64 },
+    Directional { direction: Triple, irradiance: Triple }
+}
+
+fn camera_settings(table: Spanned<CameraTable>, invalid: &impl Fn(Range<usize>, String) -> SceneError) -> Result<CameraSettings, SceneError> {
+    let span = table.span();
+    let table = table.into_inner();
+    let mut settings = CameraSettings::default();
+
+    if let Some(aspect_ratio) = table.aspect_ratio { settings.aspect_ratio = aspect_ratio; }
+    if let Some(image_width) = table.image_width { settings.image_width = image_width; }
+    if let Some(samples_per_pixel) = table.samples_per_pixel { settings.samples_per_pixel = samples_per_pixel; }
+    if let Some(max_depth) = table.max_depth { settings.max_depth = max_depth; }
+    if let Some(vfov) = table.vfov { settings.vfov = vfov; }
+    if let Some(look_from) = table.look_from { settings.look_from = point(look_from); }
+    if let Some(vup) = table.vup { settings.vup = point(vup); }
+    if let Some(defocus_angle) = table.defocus_angle { settings.defocus_angle = defocus_angle; }
+    if let Some(focus_dist) = table.focus_dist { settings.focus_dist = focus_dist; }
+
+    if let Some(background) = table.background {
+        let background_span = background.span();
+
+        settings.background = match background.into_inner() {
+            BackgroundValue::Colour(colour) => Background::Solid(point(colour)),
+            BackgroundValue::Named(name) if name == "sky" => Background::Sky,
+            BackgroundValue::Named(name) => {
+                return Err(invalid(background_span, format!("background must be \"sky\" or a colour, not \"{}\"", name)));
+            }
+        };
+    }
+
+    // a zero-length view direction is worth pointing straight at, the rest is reported against
+    // the camera table as a whole
+    if let Some(look_at) = table.look_at {
+        let look_at_span = look_at.span();
+        settings.look_at = point(look_at.into_inner());
+
+        if (settings.look_from - settings.look_at).near_zero() {
+            return Err(invalid(look_at_span, "look_at is the same point as look_from, so there's no view direction".to_string()));
+        }
+    }
+
+    settings.validate().map_err(|error| invalid(span, format!("invalid camera: {}", error)))?;
+
+    Ok(settings)
+}
+
+// images that fail to load are reported against the image file rather than the table
+fn build_texture(name: &str, table: Spanned<TextureTable>, base: &Path, textures: &HashMap<String, Arc<dyn Texture>>, invalid: &impl Fn(Range<usize>, String) -> SceneError) -> Result<Arc<dyn Texture>, SceneError> {
+    let span = table.span();
+    let invalid = |message: String| invalid(span.clone(), format!("texture '{}': {}", name, message));
+
+    match table.into_inner() {
+        TextureTable::Solid { colour } => Ok(Arc::new(SolidColour::new(point(colour)))),
+        TextureTable::Checker { scale, even, odd } => {
+            if !(scale.is_finite() && scale > 0.0) {
+                return Err(invalid(format!("checker scale must be positive, not {}", scale)));
+            }
+
+            let even = find_texture(even, textures).map_err(invalid)?;
+            let odd = find_texture(odd, textures).map_err(invalid)?;
+            Ok(Arc::new(Checker::new(scale, even, odd)))
+        }
+        TextureTable::Image { path, filter } => {
+            let image = ImageTexture::load(&base.join(path))?;
+
+            Ok(Arc::new(match filter {
+                Some(FilterValue::Bilinear) => image.with_filter(TextureFilter::Bilinear),
+                Some(FilterValue::Trilinear) | None => image.with_filter(TextureFilter::Trilinear),
+                Some(FilterValue::Ewa) => image.with_filter(TextureFilter::Ewa)
+            }))
+        }
+        TextureTable::Turbulence(table) => noise_texture(NoisePattern::Turbulence, table).map_err(invalid),
+        TextureTable::Marble(table) => noise_texture(NoisePattern::Marble, table).map_err(invalid),
+        TextureTable::Wood(table) => noise_texture(NoisePattern::Wood, table).map_err(invalid),
+        TextureTable::Granite(table) => noise_texture(NoisePattern::Granite, table).map_err(invalid)
+    }
+}
+
+fn noise_texture(pattern: NoisePattern, table: NoiseTable) -> Result<Arc<dyn Texture>, String> {
+    if !(table.scale.is_finite() && table.scale > 0.0) {
+        return Err(format!("scale must be positive, not {}", table.scale));
+    }
+
+    let ramp = match table.ramp {
+        Some(stops) if stops.is_empty() => return Err("ramp needs at least one stop".to_string()),
+        Some(stops) => ColourRamp::new(stops.into_iter().map(|stop| (stop.at, point(stop.colour))).collect()),
+        None => pattern.default_ramp()
+    };
+    let turbulence = table.turbulence.unwrap_or(pattern.default_turbulence());
+
+    Ok(Arc::new(NoiseTexture::new(pattern, table.scale, turbulence, ramp, table.seed)))
+}
+
+fn find_texture(value: TextureValue, textures: &HashMap<String, Arc<dyn Texture>>) -> Result<Arc<dyn Texture>, String> {
+    match value {
+        TextureValue::Colour(colour) => Ok(Arc::new(SolidColour::new(point(colour)))),
+        TextureValue::Named(name) => match textures.get(&name) {
+            Some(texture) => Ok(Arc::clone(texture)),
+            None => Err(format!("unknown texture '{}'", name))
+        }
+    }
+}
+
+fn build_material(table: MaterialTable, textures: &HashMap<String, Arc<dyn Texture>>) -> Result<Arc<dyn Material>, String> {
+    match table {
+        MaterialTable::Matte { albedo } => Ok(Arc::new(Matte::textured(find_texture(albedo, textures)?))),
+        MaterialTable::Metal { albedo, fuzz } => {
+            if !(0.0..=1.0).contains(&fuzz) {
+                return Err(format!("fuzz must be between 0 and 1, not {}", fuzz));
+            }
+
+            Ok(Arc::new(Metal::textured(find_texture(albedo, textures)?, fuzz)))
+        }
+        MaterialTable::Dielectric { ior } => {
+            if !(ior.is_finite() && ior > 0.0) {
+                return Err(format!("ior must be positive, not {}", ior));
+            }
+
+            Ok(Arc::new(Dielectric::new(ior)))
+        }
+        MaterialTable::Light { emit } => {
+            if emit.iter().any(|&component| component < 0.0) {
+                return Err("emit can't be negative".to_string());
+            }
+
+            Ok(Arc::new(DiffuseLight::new(point(emit))))
+        }
+    }
+}
+
+fn build_light(table: LightTable) -> Result<Box<dyn Light>, String> {
+    let non_negative = |name: &str, colour: Triple| {
+        if colour.iter().any(|&component| component < 0.0) {
+            return Err(format!("light {} can't be negative", name));
+        }
+        Ok(point(colour))
+    };
+    let direction = |direction: Triple| {
+        let direction = point(direction);
+        if direction.near_zero() {
+            return Err("light direction can't be zero".to_string());
+        }
+        Ok(direction)
+    };
+
+    match table {
+        LightTable::Point { position, intensity } => {
+            Ok(Box::new(PointLight::new(point(position), non_negative("intensity", intensity)?)))
+        }
+        LightTable::Spot { position, direction: spot_direction, intensity, inner_angle, outer_angle } => {
+            if !(0.0..=180.0).contains(&outer_angle) || !(0.0..=outer_angle).contains(&inner_angle) {
+                return Err(format!("spot angles need 0 <= inner_angle <= outer_angle <= 180, not {} and {}", inner_angle, outer_angle));
+            }
+
+            Ok(Box::new(SpotLight::new(point(position), direction(spot_direction)?, non_negative("intensity", intensity)?, inner_angle, outer_angle)))
+        }
+        LightTable::Directional { direction: sun_direction, irradiance } => {
+            Ok(Box::new(DirectionalLight::new(direction(sun_direction)?, non_negative("irradiance", irradiance)?)))
+        }
+    }
+}
+
+fn point([x, y, z]: Triple) -> Vector3 {
+    Point3::new(x, y, z)
+}
+
+// 1-based line of the start of a span
+fn line_of(source: &str, span: Range<usize>) -> usize {
+    let start = span.start.min(source.len());
+
+    source.as_bytes()[..start].iter().filter(|&&byte| byte == b'\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_line(source: &str) -> (usize, String) {
+        match parse_scene(source, Path::new("test.toml")) {
+            Err(SceneError::Invalid { line, message, .. }) => (line, message),
+            Err(error) => panic!("unexpected error {}", error),
+            Ok(_) => panic!("scene should have been rejected")
+        }
+    }
+
+    const MATERIALS: &str = "[materials.red]\ntype = \"matte\"\nalbedo = [0.9, 0.1, 0.1]\n\n";
+
+    #[test]
+    fn builds_objects_and_camera() {
+        let source = format!("[camera]\nimage_width = 64\nvfov = 40\n\n{}[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"red\"\n", MATERIALS);
+        let scene = parse_scene(&source, Path::new("test.toml")).unwrap();
+
+        assert_eq!(scene.world.len(), 1);
+        assert_eq!(scene.camera.image_width, 64);
+        assert_eq!(scene.camera.vfov, 40.0);
+        assert_eq!(scene.camera.samples_per_pixel, CameraSettings::default().samples_per_pixel);
+    }
+
+    #[test]
+    fn reports_unknown_material_line() {
+        let source = format!("{}[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"blue\"\n", MATERIALS);
+        let (line, message) = error_line(&source);
+
+        assert_eq!(line, 5);
+        assert!(message.contains("unknown material 'blue'"));
+    }
+
+    #[test]
+    fn reports_negative_radius_line() {
+        let source = format!("{}\n\n[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = -2\nmaterial = \"red\"\n", MATERIALS);
+        let (line, message) = error_line(&source);
+
+        assert_eq!(line, 7);
+        assert!(message.contains("radius"));
+    }
+
+    #[test]
+    fn reports_zero_length_view_line() {
+        let (line, message) = error_line("[camera]\nlook_from = [1, 2, 3]\nlook_at = [1, 2, 3]\n");
+
+        assert_eq!(line, 3);
+        assert!(message.contains("view direction"));
+    }
+
+    #[test]
+    fn resolves_textures_by_name() {
+        let textures = "[textures.white]\ntype = \"solid\"\ncolour = [1, 1, 1]\n\n[textures.board]\ntype = \"checker\"\nscale = 0.5\neven = \"white\"\nodd = [0, 0, 0]\n\n";
+        let source = format!("{}[materials.floor]\ntype = \"matte\"\nalbedo = \"board\"\n", textures);
+        assert!(parse_scene(&source, Path::new("test.toml")).is_ok());
+
+        let (line, message) = error_line(&format!("{}[materials.floor]\ntype = \"metal\"\nalbedo = \"marble\"\n", textures));
+        assert_eq!(line, 11);
+        assert!(message.contains("unknown texture 'marble'"));
+    }
+
+    #[test]
+    fn builds_noise_textures() {
+        let source = "[textures.stone]\ntype = \"marble\"\nscale = 4\nseed = 7\nramp = [{ at = 0, colour = [0, 0, 0] }, { at = 1, colour = [1, 1, 1] }]\n\n[textures.oak]\ntype = \"wood\"\n";
+        assert!(parse_scene(source, Path::new("test.toml")).is_ok());
+
+        let (line, message) = error_line("[textures.oak]\ntype = \"wood\"\nscale = 0\n");
+        assert_eq!(line, 1);
+        assert!(message.contains("scale must be positive"));
+    }
+
+    #[test]
+    fn builds_flat_shapes() {
+        let objects = "[[objects]]\ntype = \"quad\"\ncorner = [0, 0, 0]\nu = [1, 0, 0]\nv = [0, 0, 1]\nmaterial = \"red\"\n\n\
+            [[objects]]\ntype = \"disk\"\ncenter = [0, 2, 0]\nnormal = [0, -1, 0]\nradius = 0.5\nmaterial = \"red\"\n\n\
+            [[objects]]\ntype = \"plane\"\npoint = [0, -1, 0]\nnormal = [0, 1, 0]\nmaterial = \"red\"\n";
+        let scene = parse_scene(&format!("{}{}", MATERIALS, objects), Path::new("test.toml")).unwrap();
+        assert_eq!(scene.world.len(), 3);
+
+        let source = format!("{}[[objects]]\ntype = \"box\"\ncorners = [[0, 0, 0], [1, 1, 0]]\nmaterial = \"red\"\n", MATERIALS);
+        assert!(error_line(&source).1.contains("differ along every axis"));
+
+        let source = format!("{}[[objects]]\ntype = \"quad\"\ncorner = [0, 0, 0]\nu = [1, 0, 0]\nv = [2, 0, 0]\nmaterial = \"red\"\n", MATERIALS);
+        let (line, message) = error_line(&source);
+        assert_eq!(line, 5);
+        assert!(message.contains("parallel"));
+    }
+
+    #[test]
+    fn builds_and_checks_lights() {
+        let scene = parse_scene("[[lights]]\ntype = \"point\"\nposition = [0, 5, 0]\nintensity = [10, 10, 10]\n", Path::new("test.toml")).unwrap();
+        assert_eq!(scene.lights.analytic.len(), 1);
+
+        let (line, message) = error_line("\n[[lights]]\ntype = \"spot\"\nposition = [0, 5, 0]\ndirection = [0, -1, 0]\nintensity = [1, 1, 1]\ninner_angle = 40\nouter_angle = 20\n");
+        assert_eq!(line, 2);
+        assert!(message.contains("spot angles"));
+    }
+
+    #[test]
+    fn reports_syntax_and_unknown_field_lines() {
+        assert_eq!(error_line("[camera]\nimage_width = \n").0, 2);
+        assert_eq!(error_line("[camera]\n\nwidth = 10\n").0, 3);
+    }
+}
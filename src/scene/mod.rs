@@ -0,0 +1,87 @@
+pub mod builtin;
+pub mod file;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::camera::CameraSettings;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::light::{Light, Lights};
+use crate::loaders::LoadError;
+
+// A world ready to be put in a BVH and rendered, plus the camera it should be seen through. lights
+// shares the world's emissive objects, for the renderer to sample directly, along with any
+// analytic lights.
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: Lights,
+    pub camera: CameraSettings
+}
+
+impl Scene {
+    pub fn new(camera: CameraSettings) -> Scene {
+        Scene { world: HittableList::new(), lights: Lights::new(), camera }
+    }
+
+    pub fn add(&mut self, object: Arc<dyn Hittable>) {
+        self.world.add_shared(object);
+    }
+
+    // adds an object to the world that also gets sampled as a light
+    pub fn add_light(&mut self, object: Arc<dyn Hittable>) {
+        self.world.add_shared(Arc::clone(&object));
+        self.lights.add_area(object);
+    }
+
+    pub fn add_analytic_light(&mut self, light: Box<dyn Light>) {
+        self.lights.add(light);
+    }
+}
+
+// Why a scene file couldn't be loaded. Invalid covers both syntax errors and scenes that parse
+// but don't make sense, and points at the line responsible either way.
+#[derive(Debug)]
+pub enum SceneError {
+    Io { path: PathBuf, error: io::Error },
+    Invalid { path: PathBuf, line: usize, message: String },
+    Load(LoadError)
+}
+
+impl SceneError {
+    pub fn io(path: &Path, error: io::Error) -> SceneError {
+        SceneError::Io { path: path.to_path_buf(), error }
+    }
+
+    pub fn invalid(path: &Path, line: usize, message: impl Into<String>) -> SceneError {
+        SceneError::Invalid { path: path.to_path_buf(), line, message: message.into() }
+    }
+}
+
+impl From<LoadError> for SceneError {
+    fn from(error: LoadError) -> SceneError {
+        SceneError::Load(error)
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::Invalid { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            SceneError::Load(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { error, .. } => Some(error),
+            SceneError::Load(error) => Some(error),
+            SceneError::Invalid { .. } => None
+        }
+    }
+}
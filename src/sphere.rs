@@ -1,28 +1,37 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vector3::*;
 
 pub struct Sphere {
     center: Point3,
     radius: f64,
-    material : Arc<dyn Material>
+    material : Arc<dyn Material>,
+    bbox: Aabb
 }
 
 impl Sphere {
     pub fn new(center: Point3, radius : f64, material : Arc<dyn Material>) -> Self {
+        let radius_vector = Vector3::new(radius, radius, radius);
+
         Sphere {
             center,
             radius,
-            material
+            material,
+            bbox: Aabb::from_points(center - radius_vector, center + radius_vector)
         }
     }
 }
 
-impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, ray_t : Interval) -> Option<HitRecord> {
+impl Sphere {
+    // Both values of t where the ray crosses the sphere, nearest first
+    fn roots(&self, ray: &Ray) -> Option<(f64, f64)> {
         let oc = ray.origin - self.center;
         let a = ray.direction.squared_length();
         let half_b = oc.dot(ray.direction);
@@ -33,16 +42,12 @@ impl Hittable for Sphere {
             return None;
         }
 
-        let mut root = (-half_b - discriminant.sqrt()) / a;
-        if ! ray_t.surrounds(root) {
-            root = (-half_b + discriminant.sqrt()) / a;
-            if ! ray_t.surrounds(root) {
-                return None;
-            }
-        }
+        let sqrtd = discriminant.sqrt();
+        Some(((-half_b - sqrtd) / a, (-half_b + sqrtd) / a))
+    }
 
-        //if the passed in ray hits this sphere, set the t, point and normal for shading
-        let t = root;
+    //set the t, point and normal for shading where the ray hits this sphere
+    fn record(&self, ray: &Ray, t: f64) -> HitRecord {
         let point = ray.at(t);
         let outward_normal = (point - self.center) / self.radius;
 
@@ -54,7 +59,93 @@ impl Hittable for Sphere {
         );
 
         record.set_face_normal(ray, outward_normal);
+        (record.u, record.v) = sphere_uv(outward_normal);
+
+        // from the point in terms of the angles, with u = phi / 2 pi and v = theta / pi:
+        // (-r sin theta cos phi, -r cos theta, r sin theta sin phi). rho, the distance from the
+        // y axis, is kept off zero so the poles don't divide by it.
+        let Vector3 { x, y, z } = point - self.center;
+        let rho = x.hypot(z).max(1e-8 * self.radius);
+        let dpdu = Vector3::new(z, 0.0, -x) * (2.0 * PI);
+        let dpdv = Vector3::new(-x * y / rho, rho, -y * z / rho) * PI;
+        record.set_derivatives(dpdu, dpdv, dpdu / self.radius, dpdv / self.radius);
+
+        record
+    }
+}
+
+// Surface coordinates of a point on the unit sphere. u goes once around the y axis starting
+// from -x, and v runs from the bottom pole (y = -1) to the top.
+fn sphere_uv(point: Point3) -> (f64, f64) {
+    let theta = (-point.y).clamp(-1.0, 1.0).acos();
+    let phi = (-point.z).atan2(point.x) + PI;
+
+    (phi / (2.0 * PI), theta / PI)
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, ray_t : Interval) -> Option<HitRecord> {
+        let (near, far) = self.roots(ray)?;
+
+        let mut root = near;
+        if ! ray_t.surrounds(root) {
+            root = far;
+            if ! ray_t.surrounds(root) {
+                return None;
+            }
+        }
+
+        Some(self.record(ray, root))
+    }
+
+    fn collect_hits(&self, ray: &Ray, ray_t: Interval, hits: &mut Vec<HitRecord>) {
+        if let Some((near, far)) = self.roots(ray) {
+            for root in [near, far] {
+                if ray_t.surrounds(root) {
+                    hits.push(self.record(ray, root));
+                }
+            }
+        }
+    }
 
-        return Some(record);
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
     }
-}
\ No newline at end of file
+
+    // Sampling is uniform over the cone of directions from origin that hit the sphere, which
+    // wastes no samples on the far side the way picking points on the surface would. There's no
+    // cone from inside the sphere, so it can't be sampled from there.
+    fn pdf_value(&self, origin: Point3, direction: Vector3) -> f64 {
+        let distance_squared = (self.center - origin).squared_length();
+        if distance_squared <= self.radius * self.radius {
+            return 0.0;
+        }
+
+        if self.hit(&Ray::new(origin, direction), Interval::new(0.001, f64::INFINITY)).is_none() {
+            return 0.0;
+        }
+
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut Sampler) -> Vector3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.squared_length();
+        if distance_squared <= self.radius * self.radius {
+            return direction;
+        }
+
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let r1 = sampler.random();
+        let r2 = sampler.random();
+
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).sqrt();
+
+        Onb::new(direction).transform(Vector3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+}
@@ -0,0 +1,363 @@
+use std::path::Path;
+use std::sync::Arc;
+use crate::hittable::SurfaceDifferentials;
+use crate::loaders::image::{load_image, Image};
+use crate::loaders::LoadError;
+use crate::mipmap::{MipMap, TextureFilter};
+use crate::perlin::Perlin;
+use crate::vector3::{Colour, Point3};
+
+// A colour that varies over a surface. Textures are looked up with the hit's surface coordinates
+// (u, v) and its position in space, so they can either be wrapped onto a surface or fill space
+// like a solid block of material that objects are carved out of.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Colour;
+
+    // The texture averaged over the patch of surface a ray covers, so detail too fine for the
+    // image's samples to pick up blurs away instead of aliasing. Textures that don't filter use
+    // their value at the hit.
+    fn filtered_value(&self, u: f64, v: f64, point: Point3, _footprint: &SurfaceDifferentials) -> Colour {
+        self.value(u, v, point)
+    }
+}
+
+pub struct SolidColour {
+    colour: Colour
+}
+
+impl SolidColour {
+    pub fn new(colour: Colour) -> SolidColour {
+        SolidColour { colour }
+    }
+}
+
+impl Texture for SolidColour {
+    fn value(&self, _u: f64, _v: f64, _point: Point3) -> Colour {
+        self.colour
+    }
+}
+
+// A 3D checkerboard of cubes with sides of length scale, alternating between two textures. Being
+// solid, it needs no surface coordinates and looks the same on any shape.
+pub struct Checker {
+    inverse_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>
+}
+
+impl Checker {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Checker {
+        Checker { inverse_scale: 1.0 / scale, even, odd }
+    }
+
+    pub fn from_colours(scale: f64, even: Colour, odd: Colour) -> Checker {
+        Checker::new(scale, Arc::new(SolidColour::new(even)), Arc::new(SolidColour::new(odd)))
+    }
+}
+
+impl Checker {
+    fn texture_at(&self, point: Point3) -> &dyn Texture {
+        let cell = |coordinate: f64| (coordinate * self.inverse_scale).floor() as i64;
+        let sum = cell(point.x) + cell(point.y) + cell(point.z);
+
+        if sum.rem_euclid(2) == 0 { self.even.as_ref() } else { self.odd.as_ref() }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Colour {
+        self.texture_at(point).value(u, v, point)
+    }
+
+    // the squares themselves aren't filtered, only whatever is in them
+    fn filtered_value(&self, u: f64, v: f64, point: Point3, footprint: &SurfaceDifferentials) -> Colour {
+        self.texture_at(point).filtered_value(u, v, point, footprint)
+    }
+}
+
+// An image stretched over the surface's (u, v) square, with v = 0 along the bottom of the image.
+// Coordinates outside the square are clamped to its edges. Lookups are filtered over ray
+// footprints with a mip pyramid built when the texture is made, trilinearly unless another filter
+// is picked; lookups without a footprint interpolate the full size image.
+pub struct ImageTexture {
+    mipmap: MipMap,
+    filter: TextureFilter
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> ImageTexture {
+        ImageTexture { mipmap: MipMap::new(image), filter: TextureFilter::Trilinear }
+    }
+
+    pub fn load(path: &Path) -> Result<ImageTexture, LoadError> {
+        Ok(ImageTexture::new(load_image(path)?))
+    }
+
+    pub fn with_filter(mut self, filter: TextureFilter) -> ImageTexture {
+        self.filter = filter;
+        self
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: Point3) -> Colour {
+        self.mipmap.bilinear(0, u, 1.0 - v)
+    }
+
+    // the image's t runs the opposite way to v
+    fn filtered_value(&self, u: f64, v: f64, _point: Point3, footprint: &SurfaceDifferentials) -> Colour {
+        let dst_dx = (footprint.dudx, -footprint.dvdx);
+        let dst_dy = (footprint.dudy, -footprint.dvdy);
+
+        self.mipmap.filter(self.filter, u, 1.0 - v, dst_dx, dst_dy)
+    }
+}
+
+// Maps values between 0 and 1 to colours by blending linearly between stops, each a position and
+// the colour there. Values beyond the first or last stop take its colour.
+#[derive(Clone, Debug)]
+pub struct ColourRamp {
+    stops: Vec<(f64, Colour)>
+}
+
+impl ColourRamp {
+    // stops can be in any order, but there has to be at least one
+    pub fn new(mut stops: Vec<(f64, Colour)>) -> ColourRamp {
+        assert!(!stops.is_empty(), "a colour ramp needs at least one stop");
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        ColourRamp { stops }
+    }
+
+    pub fn colour(&self, t: f64) -> Colour {
+        let after = self.stops.partition_point(|&(position, _)| position <= t);
+        if after == 0 {
+            return self.stops[0].1;
+        }
+        if after == self.stops.len() {
+            return self.stops[after - 1].1;
+        }
+
+        let (start, from) = self.stops[after - 1];
+        let (end, to) = self.stops[after];
+        let blend = (t - start) / (end - start);
+
+        from * (1.0 - blend) + to * blend
+    }
+}
+
+// How a noise texture turns Perlin noise into a value for its ramp. turbulence is how strongly
+// the noise disturbs the underlying pattern; it doesn't affect Turbulence itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoisePattern {
+    // the fractal noise on its own, for rough ground and clouds
+    Turbulence,
+    // bands along z with veins pushed around by turbulence
+    Marble,
+    // concentric rings around the y axis, warped by turbulence
+    Wood,
+    // fine, high contrast speckles, with turbulence as the contrast
+    Granite
+}
+
+impl NoisePattern {
+    pub fn default_turbulence(self) -> f64 {
+        match self {
+            NoisePattern::Turbulence => 1.0,
+            NoisePattern::Marble => 10.0,
+            NoisePattern::Wood => 0.5,
+            NoisePattern::Granite => 1.5
+        }
+    }
+
+    pub fn default_ramp(self) -> ColourRamp {
+        let stops = match self {
+            NoisePattern::Turbulence => vec![
+                (0.0, Colour::new(0.0, 0.0, 0.0)),
+                (1.0, Colour::new(1.0, 1.0, 1.0))
+            ],
+            NoisePattern::Marble => vec![
+                (0.0, Colour::new(0.2, 0.2, 0.25)),
+                (0.1, Colour::new(0.55, 0.55, 0.6)),
+                (0.35, Colour::new(0.85, 0.85, 0.85)),
+                (1.0, Colour::new(0.95, 0.95, 0.93))
+            ],
+            NoisePattern::Wood => vec![
+                (0.0, Colour::new(0.55, 0.35, 0.17)),
+                (0.7, Colour::new(0.45, 0.27, 0.12)),
+                (1.0, Colour::new(0.3, 0.17, 0.07))
+            ],
+            NoisePattern::Granite => vec![
+                (0.0, Colour::new(0.15, 0.13, 0.13)),
+                (0.4, Colour::new(0.55, 0.5, 0.48)),
+                (0.7, Colour::new(0.75, 0.62, 0.58)),
+                (1.0, Colour::new(0.9, 0.88, 0.85))
+            ]
+        };
+
+        ColourRamp::new(stops)
+    }
+}
+
+// A solid texture made from Perlin noise. scale is the frequency of the pattern: features are
+// about 1 / scale across. The noise comes from seed, so the same seed gives the same texture.
+pub struct NoiseTexture {
+    noise: Perlin,
+    pattern: NoisePattern,
+    scale: f64,
+    turbulence: f64,
+    ramp: ColourRamp
+}
+
+const OCTAVES: u32 = 7;
+
+impl NoiseTexture {
+    pub fn new(pattern: NoisePattern, scale: f64, turbulence: f64, ramp: ColourRamp, seed: u64) -> NoiseTexture {
+        NoiseTexture { noise: Perlin::new(seed), pattern, scale, turbulence, ramp }
+    }
+
+    // the pattern with its usual turbulence and colours
+    pub fn with_defaults(pattern: NoisePattern, scale: f64, seed: u64) -> NoiseTexture {
+        NoiseTexture::new(pattern, scale, pattern.default_turbulence(), pattern.default_ramp(), seed)
+    }
+
+    // where the point falls on the ramp
+    fn pattern_value(&self, point: Point3) -> f64 {
+        let point = point * self.scale;
+
+        match self.pattern {
+            NoisePattern::Turbulence => self.noise.turbulence(point, OCTAVES),
+            NoisePattern::Marble => {
+                0.5 * (1.0 + (point.z + self.turbulence * self.noise.turbulence(point, OCTAVES)).sin())
+            }
+            NoisePattern::Wood => {
+                let radius = point.x.hypot(point.z) + self.turbulence * self.noise.turbulence(point, 4);
+                radius.fract()
+            }
+            NoisePattern::Granite => {
+                // a higher frequency than the other patterns, so granite's grain is finer
+                self.turbulence * self.noise.turbulence(point * 4.0, OCTAVES)
+            }
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, point: Point3) -> Colour {
+        self.ramp.colour(self.pattern_value(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::hittable::HitRecord;
+    use crate::interval::Interval;
+    use crate::loaders::image::Image;
+    use crate::material::{Material, Matte};
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+    use crate::texture::{Checker, ImageTexture, NoisePattern, NoiseTexture, Texture};
+    use crate::vector3::{random_in_interval, Colour, Point3, Vector3};
+
+    fn rgb(colour: Colour) -> (f64, f64, f64) {
+        (colour.x, colour.y, colour.z)
+    }
+
+    // 2 x 2, with a different colour in each texel: red and green along the top row, blue and
+    // white along the bottom
+    fn quadrants() -> ImageTexture {
+        let pixels = vec![
+            Colour::new(1.0, 0.0, 0.0), Colour::new(0.0, 1.0, 0.0),
+            Colour::new(0.0, 0.0, 1.0), Colour::new(1.0, 1.0, 1.0)
+        ];
+
+        ImageTexture::new(Image { width: 2, height: 2, pixels })
+    }
+
+    #[test]
+    fn checker_flips_across_cell_boundaries() {
+        let checker = Checker::from_colours(0.5, Colour::new(1.0, 1.0, 1.0), Colour::new(0.0, 0.0, 0.0));
+        let value = |x: f64, y: f64, z: f64| checker.value(0.0, 0.0, Point3::new(x, y, z)).x;
+
+        assert_eq!(value(0.1, 0.1, 0.1), 1.0);
+        assert_eq!(value(0.49, 0.1, 0.1), 1.0);
+        assert_eq!(value(0.51, 0.1, 0.1), 0.0);
+        assert_eq!(value(0.51, 0.6, 0.1), 1.0);
+
+        // cells below zero carry on alternating, rather than mirroring the ones above it
+        assert_eq!(value(-0.1, 0.1, 0.1), 0.0);
+        assert_eq!(value(-0.6, 0.1, 0.1), 1.0);
+        assert_eq!(value(-0.1, -0.1, -0.1), 0.0);
+        assert_eq!(value(-0.1, -0.1, 0.1), 1.0);
+    }
+
+    #[test]
+    fn image_v_runs_up_the_image() {
+        let texture = quadrants();
+        let value = |u: f64, v: f64| rgb(texture.value(u, v, Point3::new(0.0, 0.0, 0.0)));
+
+        // the texel centres: v = 0 is the bottom of the image
+        assert_eq!(value(0.25, 0.25), (0.0, 0.0, 1.0));
+        assert_eq!(value(0.75, 0.25), (1.0, 1.0, 1.0));
+        assert_eq!(value(0.25, 0.75), (1.0, 0.0, 0.0));
+        assert_eq!(value(0.75, 0.75), (0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn image_edges_are_clamped() {
+        let texture = quadrants();
+        let value = |u: f64, v: f64| rgb(texture.value(u, v, Point3::new(0.0, 0.0, 0.0)));
+
+        // beyond the corners and edges, rather than wrapping round to the far side
+        assert_eq!(value(-3.0, -3.0), (0.0, 0.0, 1.0));
+        assert_eq!(value(0.0, 0.0), (0.0, 0.0, 1.0));
+        assert_eq!(value(5.0, 5.0), (0.0, 1.0, 0.0));
+        assert_eq!(value(1.0, 1.0), (0.0, 1.0, 0.0));
+        assert_eq!(value(0.25, 2.0), (1.0, 0.0, 0.0));
+        assert_eq!(value(-1.0, 0.75), (1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn matte_takes_its_colour_from_the_texture() {
+        let matte: Arc<dyn Material> = Arc::new(Matte::textured(Arc::new(quadrants())));
+        let mut record = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0), 1.0, Arc::clone(&matte));
+        (record.u, record.v) = (0.75, 0.25);
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0));
+
+        let (attenuation, _) = matte.scatter(&ray, &record, &mut Sampler::new(0)).unwrap();
+        assert_eq!(rgb(attenuation), (1.0, 1.0, 1.0));
+
+        (record.u, record.v) = (0.25, 0.75);
+        let (attenuation, _) = matte.scatter(&ray, &record, &mut Sampler::new(0)).unwrap();
+        assert_eq!(rgb(attenuation), (1.0, 0.0, 0.0));
+    }
+
+    // whether every channel of colour lies between the darkest and brightest stop of the ramp
+    fn within(colour: Colour, stops: &[Colour]) -> bool {
+        let channel = |colour: &Colour, index: usize| [colour.x, colour.y, colour.z][index];
+
+        (0..3).all(|index| {
+            let min = stops.iter().map(|stop| channel(stop, index)).fold(f64::INFINITY, f64::min);
+            let max = stops.iter().map(|stop| channel(stop, index)).fold(f64::NEG_INFINITY, f64::max);
+            (min - 1e-12..=max + 1e-12).contains(&channel(&colour, index))
+        })
+    }
+
+    #[test]
+    fn noise_colours_stay_within_their_ramps() {
+        let mut sampler = Sampler::new(11);
+
+        for pattern in [NoisePattern::Marble, NoisePattern::Wood, NoisePattern::Granite] {
+            let ramp = pattern.default_ramp();
+            let stops: Vec<Colour> = ramp.stops.iter().map(|&(_, colour)| colour).collect();
+            let texture = NoiseTexture::with_defaults(pattern, 2.0, 4);
+
+            for _ in 0..2000 {
+                let point = random_in_interval(Interval::new(-20.0, 20.0), &mut sampler);
+                let colour = texture.value(0.0, 0.0, point);
+                assert!(within(colour, &stops), "{:?} gave {:?}", pattern, (colour.x, colour.y, colour.z));
+            }
+        }
+    }
+}
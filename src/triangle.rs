@@ -0,0 +1,279 @@
+use std::sync::Arc;
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vector3::*;
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normal: Vector3, // geometric normal, facing the side the vertices wind counter-clockwise from
+    area: f64,
+    material: Arc<dyn Material>,
+    bbox: Aabb
+}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, material: Arc<dyn Material>) -> Triangle {
+        let bbox = Aabb::union(Aabb::from_points(v0, v1), Aabb::from_points(v1, v2));
+
+        let cross = (v1 - v0).cross(v2 - v0);
+
+        Triangle {
+            vertices: [v0, v1, v2],
+            normal: cross.unit(),
+            area: 0.5 * cross.length(),
+            material,
+            bbox
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let (t, barycentric) = intersect(ray, &self.vertices, ray_t)?;
+        let [v0, v1, v2] = self.vertices;
+        let (u, v) = barycentric;
+
+        // interpolating the vertices is more precise than stepping along the ray
+        let point = v0 * (1.0 - u - v) + v1 * u + v2 * v;
+
+        let mut record = HitRecord::new(point, self.normal, t, Arc::clone(&self.material));
+        record.barycentric = barycentric;
+        record.set_face_normal(ray, self.normal);
+
+        // with no texture coordinates of its own, the triangle is textured by its barycentric
+        // weights, which run along the edges from v0
+        record.u = u;
+        record.v = v;
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        record.set_derivatives(v1 - v0, v2 - v0, zero, zero);
+
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // sampled uniformly by area
+    fn pdf_value(&self, origin: Point3, direction: Vector3) -> f64 {
+        let ray = Ray::new(origin, direction);
+        match self.hit(&ray, Interval::new(0.001, f64::INFINITY)) {
+            Some(record) => area_pdf(&ray, record.t, record.normal, self.area),
+            None => 0.0
+        }
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut Sampler) -> Vector3 {
+        random_point(&self.vertices, sampler) - origin
+    }
+}
+
+// Converts the density of picking a point uniformly from a surface of the given area into a
+// density over the solid angle seen from the ray's origin, for the point the ray hits at t. The
+// normal has to be the surface's geometric one, not a smooth shading normal: it sets how much
+// solid angle the surface's area covers.
+pub fn area_pdf(ray: &Ray, t: f64, normal: Vector3, area: f64) -> f64 {
+    let distance_squared = t * t * ray.direction.squared_length();
+    let cosine = (ray.direction.dot(normal) / (ray.direction.length() * normal.length())).abs();
+
+    if cosine < 1e-8 || area <= 0.0 {
+        return 0.0;
+    }
+
+    distance_squared / (cosine * area)
+}
+
+// Point picked uniformly from the surface of a triangle
+pub fn random_point(vertices: &[Point3; 3], sampler: &mut Sampler) -> Point3 {
+    let r1 = sampler.random().sqrt();
+    let r2 = sampler.random();
+    let [v0, v1, v2] = *vertices;
+
+    v0 * (1.0 - r1) + v1 * (r1 * (1.0 - r2)) + v2 * (r1 * r2)
+}
+
+// Watertight ray/triangle intersection (Woop, Benthin and Wald, 2013). The triangle is moved into
+// a space where the ray starts at the origin and points down +z, so the test reduces to 2D edge
+// functions that are evaluated the same way for every triangle sharing an edge: rays can't slip
+// through the cracks between neighbouring triangles of a mesh.
+// Returns t and the barycentric weights of v1 and v2 (v0's weight is 1 - u - v).
+pub fn intersect(ray: &Ray, vertices: &[Point3; 3], ray_t: Interval) -> Option<(f64, (f64, f64))> {
+    let direction = ray.direction;
+
+    // kz is the dimension where the ray direction is largest, kx and ky the other two, swapped to
+    // keep the winding order when the direction is negative
+    let kz = direction.max_dimension();
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if direction[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // shear constants
+    let sx = direction[kx] / direction[kz];
+    let sy = direction[ky] / direction[kz];
+    let sz = 1.0 / direction[kz];
+
+    // vertices relative to the ray origin, sheared into ray space
+    let a = vertices[0] - ray.origin;
+    let b = vertices[1] - ray.origin;
+    let c = vertices[2] - ray.origin;
+
+    let ax = a[kx] - sx * a[kz];
+    let ay = a[ky] - sy * a[kz];
+    let bx = b[kx] - sx * b[kz];
+    let by = b[ky] - sy * b[kz];
+    let cx = c[kx] - sx * c[kz];
+    let cy = c[ky] - sy * c[kz];
+
+    // scaled barycentric coordinates from the edge functions
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    // the ray passes inside only if all edge functions agree in sign, either sign is fine since
+    // triangles are two sided
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    // A ray exactly along an edge, or through a vertex, has the edge function at zero in every
+    // triangle sharing it. Those edges only count as inside when they point one way once the
+    // triangle's winding as seen along the ray is undone. Neighbouring triangles run their shared
+    // edge in opposite directions, so exactly one of them reports the hit.
+    let winding = det.signum();
+    let owns_edge = |value: f64, (px, py): (f64, f64), (qx, qy): (f64, f64)| {
+        let (dx, dy) = (winding * (qx - px), winding * (qy - py));
+        value != 0.0 || dy > 0.0 || (dy == 0.0 && dx < 0.0)
+    };
+    if !(owns_edge(u, (cx, cy), (bx, by)) && owns_edge(v, (ax, ay), (cx, cy)) && owns_edge(w, (bx, by), (ax, ay))) {
+        return None;
+    }
+
+    // scaled hit distance, only divided by det once we know the hit is in range
+    let az = sz * a[kz];
+    let bz = sz * b[kz];
+    let cz = sz * c[kz];
+    let t = (u * az + v * bz + w * cz) / det;
+
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+
+    Some((t, (v / det, w / det)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::material::Matte;
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+    use crate::triangle::{intersect, Triangle};
+    use crate::vector3::{Colour, Point3, Vector3};
+
+    // the triangles of a fan around the origin in the z = 0 plane, all wound the same way, with
+    // their outer corners on the square from -1 to 1
+    fn fan() -> Vec<[Point3; 3]> {
+        let ring = [(1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (-1.0, 1.0), (-1.0, 0.0), (-1.0, -1.0), (0.0, -1.0), (1.0, -1.0)];
+        let corner = |(x, y): (f64, f64)| Point3::new(x, y, 0.0);
+
+        (0..ring.len())
+            .map(|i| [Point3::new(0.0, 0.0, 0.0), corner(ring[i]), corner(ring[(i + 1) % ring.len()])])
+            .collect()
+    }
+
+    fn hit_count(triangles: &[[Point3; 3]], ray: &Ray) -> usize {
+        triangles.iter().filter(|vertices| intersect(ray, vertices, Interval::new(0.001, f64::INFINITY)).is_some()).count()
+    }
+
+    #[test]
+    fn shared_edges_and_vertices_are_hit_exactly_once() {
+        let triangles = fan();
+
+        // straight through the centre, the middle of the spokes and along the outer corners'
+        // spokes, from either side
+        let targets = [(0.0, 0.0), (0.5, 0.0), (0.5, 0.5), (0.0, -0.25), (-0.75, 0.75), (-0.5, 0.0)];
+        for (x, y) in targets {
+            for z in [1.0, -1.0] {
+                let ray = Ray::new(Point3::new(x, y, z), Vector3::new(0.0, 0.0, -z));
+                assert_eq!(hit_count(&triangles, &ray), 1, "ray through ({}, {}) from z = {}", x, y, z);
+            }
+        }
+    }
+
+    #[test]
+    fn rays_across_shared_edges_never_slip_through() {
+        let triangles = fan();
+        let mut sampler = Sampler::new(1);
+
+        // from all over at points along the spokes, which aren't exactly representable
+        for _ in 0..10000 {
+            let angle = (sampler.random() * 8.0).floor() * std::f64::consts::FRAC_PI_4;
+            let distance = 0.05 + 0.9 * sampler.random();
+            let target = Point3::new(angle.cos() * distance, angle.sin() * distance, 0.0);
+            let origin = Point3::new(4.0 * sampler.random() - 2.0, 4.0 * sampler.random() - 2.0, if sampler.random() < 0.5 { 3.0 } else { -3.0 });
+
+            let ray = Ray::new(origin, target - origin);
+            assert_eq!(hit_count(&triangles, &ray), 1, "ray from {:?} to {:?}", origin, target);
+        }
+    }
+
+    #[test]
+    fn back_faces_are_hit_and_flagged() {
+        let material = Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)));
+        let triangle = Triangle::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0), material);
+
+        // the vertices wind counter-clockwise seen from +z
+        let front = Ray::new(Point3::new(0.25, 0.25, 1.0), Vector3::new(0.0, 0.0, -1.0));
+        let back = Ray::new(Point3::new(0.25, 0.25, -1.0), Vector3::new(0.0, 0.0, 1.0));
+
+        let record = triangle.hit(&front, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!(record.front_face);
+        assert_eq!(record.normal.z, 1.0);
+
+        let record = triangle.hit(&back, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert!(!record.front_face);
+        assert_eq!(record.normal.z, -1.0);
+        assert!((record.t - 1.0).abs() < 1e-12);
+        assert!((record.barycentric.0 - 0.25).abs() < 1e-12 && (record.barycentric.1 - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn parallel_rays_miss() {
+        let vertices = [Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+        let everywhere = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+
+        // in the triangle's plane, straight through it, and just above it
+        let within = Ray::new(Point3::new(-1.0, 0.25, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let above = Ray::new(Point3::new(-1.0, 0.25, 0.1), Vector3::new(1.0, 0.0, 0.0));
+
+        assert!(intersect(&within, &vertices, everywhere).is_none());
+        assert!(intersect(&above, &vertices, everywhere).is_none());
+    }
+
+    #[test]
+    fn surface_coordinates_are_the_barycentric_weights() {
+        let material = Arc::new(Matte::new(Colour::new(0.5, 0.5, 0.5)));
+        let triangle = Triangle::new(Point3::new(1.0, 0.0, 0.0), Point3::new(3.0, 0.0, 0.0), Point3::new(1.0, 4.0, 0.0), material);
+
+        let ray = Ray::new(Point3::new(1.5, 1.0, 1.0), Vector3::new(0.0, 0.0, -1.0));
+        let record = triangle.hit(&ray, Interval::new(0.001, f64::INFINITY)).unwrap();
+
+        assert!((record.u - 0.25).abs() < 1e-12 && (record.v - 0.25).abs() < 1e-12);
+        assert_eq!((record.dpdu.x, record.dpdu.y, record.dpdu.z), (2.0, 0.0, 0.0));
+        assert_eq!((record.dpdv.x, record.dpdv.y, record.dpdv.z), (0.0, 4.0, 0.0));
+        assert!(record.dndu.near_zero() && record.dndv.near_zero());
+    }
+}
@@ -1,9 +1,9 @@
-use crate::interval::Interval;
+// Combines a seed with a value (such as a pixel index) into a new, well-mixed seed using the
+// splitmix64 finaliser, so neighbouring values don't produce correlated random streams
+pub fn mix_seed(seed: u64, value: u64) -> u64 {
+    let mut z = seed ^ value.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
 
-pub fn random() -> f64 {
-    rand::random()
+    z ^ (z >> 31)
 }
-
-pub fn random_in_interval(range : Interval) -> f64 {
-    range.min + (range.max - range.min) * random()
-}
\ No newline at end of file
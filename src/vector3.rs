@@ -1,7 +1,6 @@
 use std::ops;
-use crate::camera::MAX_COLOUR;
 use crate::interval::Interval;
-use crate::util;
+use crate::sampler::Sampler;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Vector3 {
@@ -42,13 +41,26 @@ impl Vector3 {
         self / self.length()
     }
 
+    // index (0 = x, 1 = y, 2 = z) of the component with the largest magnitude
+    pub fn max_dimension(&self) -> usize {
+        let (x, y, z) = (self.x.abs(), self.y.abs(), self.z.abs());
+
+        if x > y && x > z {
+            0
+        } else if y > z {
+            1
+        } else {
+            2
+        }
+    }
+
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
         (f64::abs(self.x) < s) && (f64::abs(self.y) < s) && (f64::abs(self.z) < s)
     }
 
     pub fn reflect(&self, n : &Vector3) -> Vector3 {
-        return *self - *n * Vector3::dot(*self, *n)*2.0;
+        *self - *n * Vector3::dot(*self, *n)*2.0
     }
 
     // TODO write out explanation for this
@@ -58,49 +70,28 @@ impl Vector3 {
         let r_out_perpendicular = (*n * cos_theta + *self) * etai_over_etat;
         let r_out_parallel = *n * -f64::abs(1.0 - r_out_perpendicular.squared_length()).sqrt();
 
-        return r_out_perpendicular + r_out_parallel;
-    }
-}
-
-impl Colour {
-    pub fn to_string(&self, samples_per_pixel : i32) -> String {
-        let scale = 1.0 / samples_per_pixel as f64;
-        let r = linear_to_gamma(self.x * scale);
-        let g = linear_to_gamma(self.y * scale);
-        let b = linear_to_gamma(self.z * scale);
-
-        let intensity = Interval::new(0.000, 0.999);
-        return ((intensity.clamp(r) * MAX_COLOUR) as i32).to_string() + " " +
-            &*((intensity.clamp(g) * MAX_COLOUR) as i32).to_string() + " " +
-            &*((intensity.clamp(b) * MAX_COLOUR) as i32).to_string() + "\n"
+        r_out_perpendicular + r_out_parallel
     }
 }
 
-// images rendered are darker than expected because they are expected to be in "gamma space",
-// meaning it assumes the image has been transformed. transform linear to gamma so that the renderer
-// displays the expected image https://raytracing.github.io/books/RayTracingInOneWeekend.html#diffusematerials/usinggammacorrectionforaccuratecolorintensity
-pub fn linear_to_gamma(linear_component : f64) -> f64 {
-    return linear_component.sqrt();
-}
-
-pub fn random() -> Vector3 {
-    return Vector3::new(
-        util::random(),
-        util::random(),
-        util::random())
+pub fn random(sampler : &mut Sampler) -> Vector3 {
+    Vector3::new(
+        sampler.random(),
+        sampler.random(),
+        sampler.random())
 }
 
-pub fn random_in_interval(range : Interval) -> Vector3 {
-    return Vector3::new(
-        util::random_in_interval(range),
-        util::random_in_interval(range),
-        util::random_in_interval(range)
+pub fn random_in_interval(range : Interval, sampler : &mut Sampler) -> Vector3 {
+    Vector3::new(
+        sampler.random_in_interval(range),
+        sampler.random_in_interval(range),
+        sampler.random_in_interval(range)
     )
 }
 
-pub fn random_in_unit_sphere() -> Vector3 {
+pub fn random_in_unit_sphere(sampler : &mut Sampler) -> Vector3 {
     loop {
-        let p = random_in_interval(Interval::new(-1.0, 1.0));
+        let p = random_in_interval(Interval::new(-1.0, 1.0), sampler);
 
         if p.squared_length() < 1.0 {
             return p;
@@ -108,25 +99,25 @@ pub fn random_in_unit_sphere() -> Vector3 {
     }
 }
 
-pub fn random_unit_vector() -> Vector3 {
-    return Vector3::unit(random_in_unit_sphere());
+pub fn random_unit_vector(sampler : &mut Sampler) -> Vector3 {
+    Vector3::unit(random_in_unit_sphere(sampler))
 }
 
-pub fn random_on_hemisphere(normal : Vector3) -> Vector3 {
-    let on_unit_sphere = random_unit_vector();
+pub fn random_on_hemisphere(normal : Vector3, sampler : &mut Sampler) -> Vector3 {
+    let on_unit_sphere = random_unit_vector(sampler);
 
-    return if on_unit_sphere.dot(normal) > 0.0 {
+    if on_unit_sphere.dot(normal) > 0.0 {
         on_unit_sphere
     } else {
         -on_unit_sphere
     }
 }
 
-pub fn random_in_unit_disk() -> Vector3 {
+pub fn random_in_unit_disk(sampler : &mut Sampler) -> Vector3 {
     loop {
         let p = Vector3::new(
-            util::random_in_interval(Interval::new(-1.0, 1.0)),
-            util::random_in_interval(Interval::new(-1.0, 1.0)),
+            sampler.random_in_interval(Interval::new(-1.0, 1.0)),
+            sampler.random_in_interval(Interval::new(-1.0, 1.0)),
             0.0
         );
 
@@ -185,6 +176,19 @@ impl ops::Mul<f64> for Vector3 {
     fn mul(self, f: f64) -> Vector3 { Vector3::new(self.x * f, self.y * f, self.z * f)}
 }
 
+// components by axis: 0 = x, 1 = y, 2 = z
+impl ops::Index<usize> for Vector3 {
+    type Output = f64;
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vector3 axis out of range: {}", axis)
+        }
+    }
+}
+
 impl ops::Neg for Vector3 {
     type Output = Vector3;
     fn neg(self) -> Vector3 {